@@ -1,5 +1,751 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
 use crate::tasks::helper::get_lines;
 
+///The width of the CRT in pixels.
+pub const SCREEN_WIDTH: usize = 40;
+
+///The height of the CRT in pixels.
+pub const SCREEN_HEIGHT: usize = 6;
+
+///Errors from parsing a program.  Lines are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpuError {
+	UnknownOpcode { line: usize, opcode: String },
+	WrongOperandCount { line: usize, expected: usize, found: usize },
+}
+
+impl fmt::Display for CpuError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			CpuError::UnknownOpcode { line, opcode } => write!(f, "line {}: unknown opcode {}", line, opcode),
+			CpuError::WrongOperandCount { line, expected, found } =>
+				write!(f, "line {}: expected {} operands, found {}", line, expected, found),
+		}
+	}
+}
+
+impl std::error::Error for CpuError {}
+
+///The registers of the CPU, looked up by name.  Registers that were never written read as 0,
+///except for X which starts at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registers {
+	values: HashMap<String, i64>,
+}
+
+impl Default for Registers {
+	fn default() -> Registers { Registers { values: HashMap::from([("X".to_string(), 1)]) } }
+}
+
+impl Registers {
+	pub fn get(&self, register: &str) -> i64 { self.values.get(register).copied().unwrap_or(0) }
+
+	pub fn set(&mut self, register: &str, value: i64) { self.values.insert(register.to_string(), value); }
+
+	///Returns the X register, which holds the middle of the sprite.
+	pub fn x(&self) -> i64 { self.get("X") }
+}
+
+///An operand of an instruction, either a number or the name of a register.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+	Value(i64),
+	Register(String),
+}
+
+impl Operand {
+	///Returns the number, or the current value of the register.
+	pub fn value(&self, registers: &Registers) -> i64 {
+		return match self {
+			Operand::Value(value) => *value,
+			Operand::Register(register) => registers.get(register),
+		}
+	}
+}
+
+impl fmt::Display for Operand {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Operand::Value(value) => write!(f, "{}", value),
+			Operand::Register(register) => write!(f, "{}", register),
+		}
+	}
+}
+
+///A single line of a program, such as "addx 3".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+	pub opcode: String,
+	pub operands: Vec<Operand>,
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.opcode)?;
+		for operand in &self.operands { write!(f, " {}", operand)?; }
+
+		return Ok(());
+	}
+}
+
+///The behaviour of an instruction.  Its effect on the registers only applies at the very end
+///of its last cycle, so observers see the old register values during every one of its cycles.
+pub trait Opcode {
+	fn operand_count(&self) -> usize;
+
+	fn cycles(&self) -> u32;
+
+	fn execute(&self, operands: &[Operand], registers: &mut Registers);
+}
+
+///Does nothing for one cycle.
+pub struct Noop;
+
+impl Opcode for Noop {
+	fn operand_count(&self) -> usize { 0 }
+
+	fn cycles(&self) -> u32 { 1 }
+
+	fn execute(&self, _: &[Operand], _: &mut Registers) {}
+}
+
+///Adds its operand to a register over two cycles, such as "addx 3" for the X register.
+pub struct Add {
+	pub register: String,
+}
+
+impl Opcode for Add {
+	fn operand_count(&self) -> usize { 1 }
+
+	fn cycles(&self) -> u32 { 2 }
+
+	fn execute(&self, operands: &[Operand], registers: &mut Registers) {
+		let value = registers.get(&self.register) + operands[0].value(registers);
+		registers.set(&self.register, value);
+	}
+}
+
+///The opcodes the CPU understands, looked up by their mnemonic.
+pub struct InstructionSet {
+	opcodes: HashMap<String, Box<dyn Opcode>>,
+}
+
+impl Default for InstructionSet {
+	///The instruction set of the handheld device: noop and addx.
+	fn default() -> InstructionSet {
+		let mut instruction_set = InstructionSet { opcodes: HashMap::new() };
+		instruction_set.add("noop", Box::new(Noop));
+		instruction_set.add("addx", Box::new(Add { register: "X".to_string() }));
+
+		return instruction_set;
+	}
+}
+
+impl InstructionSet {
+	///Adds a new opcode, or replaces the behaviour of an existing one.
+	pub fn add(&mut self, mnemonic: &str, opcode: Box<dyn Opcode>) { self.opcodes.insert(mnemonic.to_string(), opcode); }
+
+	pub fn get(&self, mnemonic: &str) -> Option<&dyn Opcode> { self.opcodes.get(mnemonic).map(|opcode| opcode.as_ref()) }
+
+	//parses a single line of a program, returning None for a blank line
+	fn parse_line(&self, line_number: usize, line: &str) -> Result<Option<Instruction>, CpuError> {
+		let mut parts = line.split_whitespace();
+		let mnemonic = match parts.next() {
+			Some(mnemonic) => mnemonic,
+			None => return Ok(None),
+		};
+
+		let opcode = self.get(mnemonic).ok_or_else(|| CpuError::UnknownOpcode { line: line_number, opcode: mnemonic.to_string() })?;
+		let operands: Vec<Operand> = parts.map(|part| match part.parse::<i64>() {
+			Ok(value) => Operand::Value(value),
+			Err(_) => Operand::Register(part.to_string()),
+		}).collect();
+
+		if operands.len() != opcode.operand_count() {
+			return Err(CpuError::WrongOperandCount { line: line_number, expected: opcode.operand_count(), found: operands.len() });
+		}
+
+		return Ok(Some(Instruction { opcode: mnemonic.to_string(), operands }));
+	}
+
+	///Parses a program, one instruction per line.  Blank lines are ignored.
+	pub fn parse(&self, lines: &[String]) -> Result<Vec<Instruction>, CpuError> {
+		let mut program = Vec::new();
+		for (index, line) in lines.iter().enumerate() {
+			if let Some(instruction) = self.parse_line(index + 1, line)? { program.push(instruction); }
+		}
+
+		return Ok(program);
+	}
+
+	///Assembles a program written in assembly text.  Each line holds an optional label, such as
+	///"start:", followed by an optional instruction, and anything after a ';' is a comment.
+	///Operands may be separated by commas.  Besides the opcodes of the instruction set, two
+	///pseudo-instructions are understood, which take precedence over opcodes of the same name:
+	///"setx n" becomes the single addx that sets X to n, and "wait n" becomes n noops.
+	pub fn assemble(&self, source: &str) -> Result<Assembly, AssemblyError> {
+		let mut assembly = Assembly { program: Vec::new(), labels: HashMap::new() };
+		let mut registers = Registers::default();  // the registers as they are after the last instruction
+		let mut cycle = 1;
+
+		for (index, line) in source.lines().enumerate() {
+			let line_number = index + 1;
+			let mut code = line.split(';').next().unwrap().replace(',', " ");
+			if let Some(colon) = code.find(':') {
+				let label = code[..colon].trim().to_string();
+				let is_identifier = label.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+					&& label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+				if !is_identifier { return Err(AssemblyError::InvalidLabel { line: line_number, label }); }
+				if assembly.labels.contains_key(&label) { return Err(AssemblyError::DuplicateLabel { line: line_number, label }); }
+
+				assembly.labels.insert(label, Label { index: assembly.program.len(), cycle });
+				code = code[colon+1..].to_string();
+			}
+
+			let mut parts = code.split_whitespace();
+			let lowered = match parts.next() {
+				None => continue,
+				Some(pseudo @ ("setx" | "wait")) => {
+					let operands: Vec<&str> = parts.collect();
+					if operands.len() != 1 {
+						return Err(CpuError::WrongOperandCount { line: line_number, expected: 1, found: operands.len() }.into());
+					}
+
+					let value = operands[0].parse::<i64>().map_err(|_| AssemblyError::InvalidOperand { line: line_number, operand: operands[0].to_string() })?;
+					if pseudo == "setx" { vec![format!("addx {}", value - registers.x())] }
+					else if value < 0 { return Err(AssemblyError::InvalidOperand { line: line_number, operand: operands[0].to_string() }); }
+					else { vec!["noop".to_string(); value as usize] }
+				}
+				Some(_) => vec![code.clone()],
+			};
+
+			for text in lowered {
+				let instruction = self.parse_line(line_number, &text)?.unwrap();
+				let opcode = self.get(&instruction.opcode).unwrap();
+				opcode.execute(&instruction.operands, &mut registers);
+				cycle += opcode.cycles().max(1) as u64;
+				assembly.program.push(instruction);
+			}
+		}
+
+		return Ok(assembly);
+	}
+}
+
+///Errors from assembling a program.  Lines are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssemblyError {
+	Cpu(CpuError),
+	InvalidLabel { line: usize, label: String },
+	DuplicateLabel { line: usize, label: String },
+	InvalidOperand { line: usize, operand: String },
+}
+
+impl fmt::Display for AssemblyError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			AssemblyError::Cpu(error) => write!(f, "{}", error),
+			AssemblyError::InvalidLabel { line, label } => write!(f, "line {}: invalid label \"{}\"", line, label),
+			AssemblyError::DuplicateLabel { line, label } => write!(f, "line {}: label {} is already defined", line, label),
+			AssemblyError::InvalidOperand { line, operand } => write!(f, "line {}: invalid operand {}", line, operand),
+		}
+	}
+}
+
+impl std::error::Error for AssemblyError {}
+
+impl From<CpuError> for AssemblyError {
+	fn from(error: CpuError) -> AssemblyError { AssemblyError::Cpu(error) }
+}
+
+///Where a label points in an assembled program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label {
+	pub index: usize,  // the index of the first instruction after the label
+	pub cycle: u64,    // the cycle that instruction starts on
+}
+
+///A program lowered from assembly text, along with the labels defined in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+	program: Vec<Instruction>,
+	labels: HashMap<String, Label>,
+}
+
+impl Assembly {
+	pub fn program(&self) -> &[Instruction] { &self.program }
+
+	pub fn into_program(self) -> Vec<Instruction> { self.program }
+
+	pub fn labels(&self) -> &HashMap<String, Label> { &self.labels }
+
+	pub fn label(&self, name: &str) -> Option<Label> { self.labels.get(name).copied() }
+
+	///Returns a breakpoint on the cycle the instruction after the label starts on.
+	pub fn breakpoint(&self, label: &str) -> Option<Breakpoint> { self.label(label).map(|label| Breakpoint::Cycle(label.cycle)) }
+
+	///Returns the program in the raw format the device reads, one instruction per line.
+	pub fn raw(&self) -> String {
+		return self.program.iter().map(|instruction| format!("{}\n", instruction)).collect();
+	}
+}
+
+///Gets called during every cycle the CPU runs, before the running instruction takes effect.
+pub trait CycleObserver {
+	fn during_cycle(&mut self, cycle: u64, registers: &Registers);
+}
+
+///Runs a program one cycle at a time.
+pub struct Cpu {
+	instruction_set: InstructionSet,
+	program: Vec<Instruction>,
+	registers: Registers,
+	cycle: u64,      // the number of the next cycle to run, starting at 1
+	pc: usize,       // the index of the instruction being run
+	remaining: u32,  // the cycles left for the instruction being run, 0 if it has not started yet
+}
+
+impl Cpu {
+	pub fn new(instruction_set: InstructionSet, program: Vec<Instruction>) -> Cpu {
+		Cpu { instruction_set, program, registers: Registers::default(), cycle: 1, pc: 0, remaining: 0 }
+	}
+
+	///Reads a program for the handheld device from a file.
+	pub fn from_file(filename: &str) -> Result<Cpu, CpuError> {
+		let instruction_set = InstructionSet::default();
+		let program = instruction_set.parse(&get_lines(filename))?;
+
+		return Ok(Cpu::new(instruction_set, program));
+	}
+
+	pub fn registers(&self) -> &Registers { &self.registers }
+
+	pub fn registers_mut(&mut self) -> &mut Registers { &mut self.registers }
+
+	///Returns the number of the next cycle to run.
+	pub fn cycle(&self) -> u64 { self.cycle }
+
+	///Returns the index of the instruction being run, or about to be run.
+	pub fn pc(&self) -> usize { self.pc }
+
+	pub fn program(&self) -> &[Instruction] { &self.program }
+
+	pub fn is_halted(&self) -> bool { self.pc >= self.program.len() }
+
+	///Runs a single cycle, notifying every observer during it.
+	///Returns false without doing anything once the program has finished.
+	pub fn step(&mut self, observers: &mut [&mut dyn CycleObserver]) -> bool {
+		if self.is_halted() { return false; }
+
+		let instruction = &self.program[self.pc];
+		let opcode = self.instruction_set.get(&instruction.opcode).expect("Instruction missing from the instruction set");
+		if self.remaining == 0 { self.remaining = opcode.cycles().max(1); }
+
+		for observer in observers.iter_mut() { observer.during_cycle(self.cycle, &self.registers); }
+
+		self.remaining -= 1;
+		if self.remaining == 0 {
+			opcode.execute(&instruction.operands, &mut self.registers);
+			self.pc += 1;
+		}
+
+		self.cycle += 1;
+		return true;
+	}
+
+	///Runs the program until it finishes.
+	pub fn run(&mut self, observers: &mut [&mut dyn CycleObserver]) {
+		while self.step(observers) {}
+	}
+}
+
+///A condition that pauses the debugger before a cycle runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+	///Pauses before the cycle with this number.
+	Cycle(u64),
+	///Pauses before any cycle during which the register holds this value.
+	Register { register: String, value: i64 },
+}
+
+impl Breakpoint {
+	fn is_hit(&self, cpu: &Cpu) -> bool {
+		return match self {
+			Breakpoint::Cycle(cycle) => cpu.cycle() == *cycle,
+			Breakpoint::Register { register, value } => cpu.registers().get(register) == *value,
+		}
+	}
+}
+
+///One cycle of an execution trace.  X is read during the cycle and at its very end, so
+///`x_after` only differs from `x_before` on the last cycle of an instruction that changes X.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+	pub cycle: u64,
+	pub pc: usize,
+	pub instruction: Instruction,
+	pub x_before: i64,
+	pub x_after: i64,
+}
+
+impl fmt::Display for TraceEntry {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "cycle {:>4}  pc {:>4}  {:<12}  X {} -> {}", self.cycle, self.pc, self.instruction.to_string(), self.x_before, self.x_after)
+	}
+}
+
+///Why the debugger stopped running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+	Breakpoint(Breakpoint),
+	Halted,
+}
+
+///Runs a CPU under control of breakpoints, one cycle or many at a time, and records
+///every cycle it runs in an execution trace.
+pub struct Debugger {
+	cpu: Cpu,
+	breakpoints: Vec<Breakpoint>,
+	trace: Vec<TraceEntry>,
+	paused_at: Option<u64>,  // the cycle the debugger last paused before, so resuming does not pause there again
+}
+
+impl Debugger {
+	pub fn new(cpu: Cpu) -> Debugger { Debugger { cpu, breakpoints: Vec::new(), trace: Vec::new(), paused_at: None } }
+
+	pub fn cpu(&self) -> &Cpu { &self.cpu }
+
+	pub fn cpu_mut(&mut self) -> &mut Cpu { &mut self.cpu }
+
+	pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) { self.breakpoints.push(breakpoint); }
+
+	///Removes a breakpoint, returning whether it was set.
+	pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+		let count = self.breakpoints.len();
+		self.breakpoints.retain(|other| other != breakpoint);
+
+		return self.breakpoints.len() != count;
+	}
+
+	pub fn breakpoints(&self) -> &[Breakpoint] { &self.breakpoints }
+
+	///Returns every cycle run so far, in order.
+	pub fn trace(&self) -> &[TraceEntry] { &self.trace }
+
+	///Runs a single cycle regardless of breakpoints, and returns its trace entry.
+	///Returns None once the program has finished.
+	pub fn step(&mut self, observers: &mut [&mut dyn CycleObserver]) -> Option<TraceEntry> {
+		if self.cpu.is_halted() { return None; }
+
+		let (cycle, pc, x_before) = (self.cpu.cycle(), self.cpu.pc(), self.cpu.registers().x());
+		let instruction = self.cpu.program()[pc].clone();
+		self.cpu.step(observers);
+
+		let entry = TraceEntry { cycle, pc, instruction, x_before, x_after: self.cpu.registers().x() };
+		self.trace.push(entry.clone());
+		self.paused_at = None;
+
+		return Some(entry);
+	}
+
+	///Runs until a breakpoint is hit or the program finishes.  A breakpoint that paused
+	///the debugger before the current cycle does not pause it again.
+	pub fn run(&mut self, observers: &mut [&mut dyn CycleObserver]) -> StopReason {
+		loop {
+			if self.cpu.is_halted() { return StopReason::Halted; }
+
+			if self.paused_at != Some(self.cpu.cycle()) {
+				if let Some(breakpoint) = self.breakpoints.iter().find(|breakpoint| breakpoint.is_hit(&self.cpu)) {
+					self.paused_at = Some(self.cpu.cycle());
+					return StopReason::Breakpoint(breakpoint.clone());
+				}
+			}
+
+			self.step(observers);
+		}
+	}
+
+	///Formats the execution trace, one cycle per line.
+	pub fn trace_report(&self) -> String {
+		return self.trace.iter().map(|entry| format!("{}\n", entry)).collect();
+	}
+
+	///Writes the execution trace to a file, one cycle per line.
+	pub fn write_trace(&self, filename: &str) -> std::io::Result<()> {
+		return fs::write(filename, self.trace_report());
+	}
+}
+
+///Sums the signal strength, the cycle number times the X register, during the 20th cycle
+///and every 40 cycles after that.
+#[derive(Default)]
+pub struct SignalStrength {
+	pub sum: i64,
+}
+
+impl CycleObserver for SignalStrength {
+	fn during_cycle(&mut self, cycle: u64, registers: &Registers) {
+		if cycle >= 20 && (cycle - 20).is_multiple_of(40) { self.sum += cycle as i64 * registers.x(); }
+	}
+}
+
+///Draws one pixel per cycle, row by row.  A pixel is lit if the three pixel wide sprite
+///centered on the X register covers it.
+#[derive(Default)]
+pub struct Crt {
+	pub screen: String,
+}
+
+impl CycleObserver for Crt {
+	fn during_cycle(&mut self, cycle: u64, registers: &Registers) {
+		let column = ((cycle - 1) % SCREEN_WIDTH as u64) as i64;
+		self.screen.push(if (registers.x() - column).abs() <= 1 { '#' } else { '.' });
+		if column == SCREEN_WIDTH as i64 - 1 { self.screen.push('\n'); }
+	}
+}
+
+///The height of a letter on the CRT in pixels.
+pub const GLYPH_HEIGHT: usize = 6;
+
+///The width of a letter on the CRT in pixels.  Letters are separated by one blank column.
+pub const GLYPH_WIDTH: usize = 4;
+
+//every letter of the CRT font that is known, as its rows from top to bottom
+const FONT: [(char, [&str; GLYPH_HEIGHT]); 18] = [
+	('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+	('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+	('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+	('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+	('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+	('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+	('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+	('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+	('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+	('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+	('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+	('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+	('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+	('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+	('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+	('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+	('Y', ["#...", "#...", ".#.#", "..#.", "..#.", "..#."]),
+	('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+///A letter on the screen that does not match any letter of the font.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownGlyph {
+	pub index: usize,                  // the position of the letter in the text, starting at 0
+	pub column: usize,                 // the screen column of its leftmost pixel
+	pub rows: [String; GLYPH_HEIGHT],  // its pixels from top to bottom
+}
+
+///Errors from reading the letters on a screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OcrError {
+	WrongHeight(usize),
+	///The text read so far, with '?' in place of every letter that was not recognized.
+	UnknownGlyphs { text: String, glyphs: Vec<UnknownGlyph> },
+}
+
+impl fmt::Display for OcrError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			OcrError::WrongHeight(height) => write!(f, "the screen is {} rows high, letters are {} rows high", height, GLYPH_HEIGHT),
+			OcrError::UnknownGlyphs { text, glyphs } => {
+				write!(f, "unknown letters in \"{}\" at", text)?;
+				for glyph in glyphs { write!(f, " index {} (column {})", glyph.index, glyph.column)?; }
+				Ok(())
+			}
+		}
+	}
+}
+
+impl std::error::Error for OcrError {}
+
+///Reads the capital letters drawn on a screen of '#' and '.' pixels, one letter every
+///`GLYPH_WIDTH + 1` columns.  Letters that are entirely dark are read as spaces.
+pub fn read_screen(screen: &str) -> Result<String, OcrError> {
+	let rows: Vec<&str> = screen.lines().filter(|line| !line.is_empty()).collect();
+	if rows.len() != GLYPH_HEIGHT { return Err(OcrError::WrongHeight(rows.len())); }
+
+	let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+	let mut text = String::new();
+	let mut glyphs = Vec::new();
+	for (index, column) in (0..width).step_by(GLYPH_WIDTH + 1).enumerate() {
+		let cell: [String; GLYPH_HEIGHT] = std::array::from_fn(|i| {
+			let row = rows[i].get(column..).unwrap_or("");
+			format!("{:.<width$}", &row[..row.len().min(GLYPH_WIDTH)], width = GLYPH_WIDTH)
+		});
+
+		if cell.iter().all(|row| row.chars().all(|c| c == '.')) {
+			text.push(' ');
+			continue;
+		}
+
+		match FONT.iter().find(|(_, glyph)| glyph.iter().zip(&cell).all(|(a, b)| a == b)) {
+			Some(&(letter, _)) => text.push(letter),
+			None => {
+				text.push('?');
+				glyphs.push(UnknownGlyph { index, column, rows: cell });
+			}
+		}
+	}
+
+	return if glyphs.is_empty() { Ok(text) }
+	else { Err(OcrError::UnknownGlyphs { text, glyphs }) }
+}
+
+///The pixels of a whole screen, row by row, true where a pixel is lit.
+pub type Bitmap = [[bool; SCREEN_WIDTH]; SCREEN_HEIGHT];
+
+///Errors from reading a bitmap and drawing it with a program.  Rows and columns start at 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DrawError {
+	WrongSize { rows: usize, cols: usize },
+	InvalidPixel { row: usize, col: usize, pixel: char },
+	///No program can draw the target, since no value of X can draw this pixel on time.
+	Undrawable { row: usize, col: usize },
+	Program(CpuError),
+	WrongCycleCount { expected: usize, found: usize },
+	///The pixels that the program drew differently from the target.
+	Mismatch(Vec<(usize, usize)>),
+}
+
+impl fmt::Display for DrawError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			DrawError::WrongSize { rows, cols } =>
+				write!(f, "the bitmap is {}x{}, the screen is {}x{}", cols, rows, SCREEN_WIDTH, SCREEN_HEIGHT),
+			DrawError::InvalidPixel { row, col, pixel } => write!(f, "invalid pixel '{}' at row {}, column {}", pixel, row, col),
+			DrawError::Undrawable { row, col } => write!(f, "the pixel at row {}, column {} cannot be drawn", row, col),
+			DrawError::Program(error) => write!(f, "{}", error),
+			DrawError::WrongCycleCount { expected, found } => write!(f, "the program runs for {} cycles instead of {}", found, expected),
+			DrawError::Mismatch(pixels) => write!(f, "{} pixels differ from the target", pixels.len()),
+		}
+	}
+}
+
+impl std::error::Error for DrawError {}
+
+///Reads a bitmap drawn with '#' for lit pixels and '.' for dark ones, in the format of `get_screen`.
+pub fn parse_bitmap(screen: &str) -> Result<Bitmap, DrawError> {
+	let rows: Vec<&str> = screen.lines().filter(|line| !line.is_empty()).collect();
+	let cols = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+	if rows.len() != SCREEN_HEIGHT || rows.iter().any(|row| row.chars().count() != SCREEN_WIDTH) {
+		return Err(DrawError::WrongSize { rows: rows.len(), cols });
+	}
+
+	let mut bitmap = [[false; SCREEN_WIDTH]; SCREEN_HEIGHT];
+	for (i, row) in rows.iter().enumerate() {
+		for (j, pixel) in row.chars().enumerate() {
+			bitmap[i][j] = match pixel {
+				'#' => true,
+				'.' => false,
+				_ => return Err(DrawError::InvalidPixel { row: i, col: j, pixel }),
+			};
+		}
+	}
+
+	return Ok(bitmap);
+}
+
+///Draws a bitmap in the format of `get_screen`.
+pub fn render_bitmap(bitmap: &Bitmap) -> String {
+	let mut screen = String::new();
+	for row in bitmap {
+		screen.extend(row.iter().map(|&lit| if lit { '#' } else { '.' }));
+		screen.push('\n');
+	}
+
+	return screen;
+}
+
+///Writes a program of addx and noop instructions that draws the target on the CRT, using as
+///few instructions as possible.  X only changes at the end of an addx, which takes two cycles,
+///so some targets, such as a lone dark pixel between two lit ones, cannot be drawn at all.
+pub fn generate_program(target: &Bitmap) -> Result<Vec<Instruction>, DrawError> {
+	//every value of X that matters, from one that lights no pixel on the left to one that lights none on the right
+	const LOWEST: i64 = -2;
+	const VALUES: usize = SCREEN_WIDTH + 4;
+	let pixels = SCREEN_WIDTH * SCREEN_HEIGHT;
+	let draws = |cycle: usize, x: i64| {
+		let col = (cycle % SCREEN_WIDTH) as i64;
+		return ((x - col).abs() <= 1) == target[cycle / SCREEN_WIDTH][cycle % SCREEN_WIDTH];
+	};
+
+	//best[cycle][x] is the fewest instructions that finish right before the cycle with X at LOWEST + x,
+	//along with the cycle and value of X that the last of them started on
+	let mut best: Vec<[Option<(usize, usize, usize)>; VALUES]> = vec![[None; VALUES]; pixels + 1];
+	best[0][(1 - LOWEST) as usize] = Some((0, 0, 0));
+	for cycle in 0..pixels {
+		for x in 0..VALUES {
+			let Some((count, _, _)) = best[cycle][x] else { continue };
+			if !draws(cycle, LOWEST + x as i64) { continue; }
+
+			if best[cycle + 1][x].is_none_or(|(best_count, _, _)| count + 1 < best_count) {
+				best[cycle + 1][x] = Some((count + 1, cycle, x));
+			}
+
+			if cycle + 2 > pixels || !draws(cycle + 1, LOWEST + x as i64) { continue; }
+			for next in best[cycle + 2].iter_mut() {
+				if next.is_none_or(|(best_count, _, _)| count + 1 < best_count) { *next = Some((count + 1, cycle, x)); }
+			}
+		}
+	}
+
+	let last = (0..VALUES).filter(|&x| best[pixels][x].is_some()).min_by_key(|&x| best[pixels][x].unwrap().0);
+	let Some(mut x) = last else {
+		let stuck = (0..pixels).rev().find(|&cycle| best[cycle].iter().any(|state| state.is_some())).unwrap();
+		return Err(DrawError::Undrawable { row: stuck / SCREEN_WIDTH, col: stuck % SCREEN_WIDTH });
+	};
+
+	let mut program = Vec::new();
+	let mut cycle = pixels;
+	while cycle > 0 {
+		let (_, previous_cycle, previous_x) = best[cycle][x].unwrap();
+		program.push(if cycle - previous_cycle == 1 { Instruction { opcode: "noop".to_string(), operands: Vec::new() } }
+			else { Instruction { opcode: "addx".to_string(), operands: vec![Operand::Value(x as i64 - previous_x as i64)] } });
+		(cycle, x) = (previous_cycle, previous_x);
+	}
+
+	program.reverse();
+	return Ok(program);
+}
+
+///Runs a program for the handheld device and checks that it draws exactly the target,
+///taking exactly as many cycles as there are pixels on the screen.
+pub fn validate_program(program: &[Instruction], target: &Bitmap) -> Result<(), DrawError> {
+	let instruction_set = InstructionSet::default();
+	for (index, instruction) in program.iter().enumerate() {
+		let opcode = instruction_set.get(&instruction.opcode)
+			.ok_or_else(|| DrawError::Program(CpuError::UnknownOpcode { line: index + 1, opcode: instruction.opcode.clone() }))?;
+		if instruction.operands.len() != opcode.operand_count() {
+			let error = CpuError::WrongOperandCount { line: index + 1, expected: opcode.operand_count(), found: instruction.operands.len() };
+			return Err(DrawError::Program(error));
+		}
+	}
+
+	let mut cpu = Cpu::new(instruction_set, program.to_vec());
+	let mut crt = Crt::default();
+	cpu.run(&mut [&mut crt]);
+
+	let drawn: Vec<bool> = crt.screen.chars().filter(|&c| c != '\n').map(|c| c == '#').collect();
+	if drawn.len() != SCREEN_WIDTH * SCREEN_HEIGHT {
+		return Err(DrawError::WrongCycleCount { expected: SCREEN_WIDTH * SCREEN_HEIGHT, found: drawn.len() });
+	}
+
+	let mismatches: Vec<(usize, usize)> = drawn.iter().enumerate()
+		.filter(|&(cycle, &lit)| lit != target[cycle / SCREEN_WIDTH][cycle % SCREEN_WIDTH])
+		.map(|(cycle, _)| (cycle / SCREEN_WIDTH, cycle % SCREEN_WIDTH))
+		.collect();
+
+	return if mismatches.is_empty() { Ok(()) }
+	else { Err(DrawError::Mismatch(mismatches)) }
+}
+
 ///The input is a file containing a series of operations, one per line.
 ///Each operation is either an addition or a noop.
 ///noop takes one cycle to complete, addition takes 2 cycles to complete.
@@ -9,27 +755,11 @@ use crate::tasks::helper::get_lines;
 ///This function returns the sum of the signal strengths at the 20th, 60th,
 ///100th, 140th, 180th, and 220th cycle.
 pub fn get_signal_strengths(input_file: &str) -> i32 {
-	let lines = get_lines(input_file);
-	let mut sum = 0;
-	let mut register = 1;
-	let mut cycle = 1;
-	for line in &lines {
-		if (cycle-20) % 40 == 0 { sum += cycle * register; }
+	let mut cpu = Cpu::from_file(input_file).expect("Invalid program");
+	let mut signal_strength = SignalStrength::default();
+	cpu.run(&mut [&mut signal_strength]);
 
-		let mut parts = line.split_whitespace();
-		match parts.next() {
-			Some("addx") => {
-				cycle += 1;
-				if (cycle-20) % 40 == 0 { sum += cycle * register; }
-				register += parts.next().unwrap().parse::<i32>().unwrap();
-				cycle += 1;
-			},
-			Some("noop") => cycle += 1,
-			_ => panic!("Invalid operation")
-		}
-	}
-
-	return sum;
+	return signal_strength.sum as i32;
 }
 
 ///The input is the same as the above function, but now the register value represents the center
@@ -37,47 +767,38 @@ pub fn get_signal_strengths(input_file: &str) -> i32 {
 ///if the sprite is currently one the location being drawn, it is filled in with a #, otherwise
 ///it is filled in with a .  This function returns the string output representing the resulting screen.
 pub fn get_screen(input_file: &str) -> String {
-	let lines = get_lines(input_file);
-	let mut screen = String::new();
-	let mut register: i32 = 1;
-	let mut cycle: i32 = 0;
-	for line in &lines {
-		let mut parts = line.split_whitespace();
-		match parts.next() {
-			Some("addx") => {
-				if (register - cycle).abs() <= 1 { screen.push('#'); }
-				else { screen.push('.'); }
-
-				cycle += 1;
-				if cycle % 40 == 0 {
-					cycle = 0;
-					screen.push('\n');
-				}
+	let mut cpu = Cpu::from_file(input_file).expect("Invalid program");
+	let mut crt = Crt::default();
+	cpu.run(&mut [&mut crt]);
 
-				if (register - cycle).abs() <= 1 { screen.push('#'); }
-				else { screen.push('.'); }
+	return crt.screen;
+}
 
-				cycle += 1;
-				if cycle % 40 == 0 {
-					cycle = 0;
-					screen.push('\n');
-				}
+///Runs the program like `get_screen` and returns the capital letters it draws.
+pub fn get_screen_text(input_file: &str) -> String {
+	return read_screen(&get_screen(input_file)).expect("Unable to read the screen");
+}
 
-				register += parts.next().unwrap().parse::<i32>().unwrap();
-			},
-			Some("noop") => {
-				if (register - cycle).abs() <= 1 { screen.push('#'); }
-				else { screen.push('.'); }
+#[cfg(test)]
+mod tests {
+	use super::*;
 
-				cycle += 1;
-				if cycle % 40 == 0 {
-					cycle = 0;
-					screen.push('\n');
-				}
-			},
-			_ => panic!("Invalid operation")
-		}
+	#[test]
+	fn reads_screen_text_of_puzzle_input() {
+		assert_eq!(get_screen_text("input/p10.txt"), "EJCFPGLH");
 	}
 
-	return screen;
-}
\ No newline at end of file
+	#[test]
+	fn reports_unknown_glyph_with_index_and_column() {
+		// lights the top left pixel of the third letter, turning C into a glyph outside the font
+		let mut rows: Vec<String> = get_screen("input/p10.txt").lines().map(str::to_string).collect();
+		rows[0].replace_range(10..11, "#");
+
+		let expected = UnknownGlyph {
+			index: 2,
+			column: 10,
+			rows: ["###.", "#..#", "#...", "#...", "#..#", ".##."].map(str::to_string),
+		};
+		assert_eq!(read_screen(&rows.join("\n")), Err(OcrError::UnknownGlyphs { text: "EJ?FPGLH".to_string(), glyphs: vec![expected] }));
+	}
+}
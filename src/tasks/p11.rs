@@ -1,55 +1,375 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use num_bigint::BigUint;
 use crate::tasks::helper::get_lines;
 
-#[derive(Default)]
-struct Monkey<'a> {
-	items: VecDeque<u64>,       // the number assigned to each item the monkey holds
-	num_inspections: u64,       // the number of times the monkey has inspected an item
-	operation: Vec<&'a str>,    // the operation performed on each item per iteration
-	test: u64,                  // if the item value after the operation is divisible by this number, the test passes
-	test_pass: usize,           // what to do if the test passes
-	test_fail: usize,           // what to do if the test fails
+///A worry level the monkeys can compute with.  Fixed width levels report overflow instead of wrapping.
+pub trait Worry: Clone {
+	fn from_u64(value: u64) -> Self;
+
+	fn try_add(&self, other: &Self) -> Option<Self>;
+
+	fn try_sub(&self, other: &Self) -> Option<Self>;
+
+	fn try_mul(&self, other: &Self) -> Option<Self>;
+
+	///Divides, rounding down, or returns None when dividing by zero.
+	fn try_div(&self, other: &Self) -> Option<Self>;
+
+	fn divided_by(&self, divisor: u64) -> Self;
+
+	fn remainder(&self, modulus: u64) -> u64;
 }
 
-impl Monkey<'_> {
-	fn perform_operation(&mut self, test_multiplied: u64) -> Vec<(usize, u64)> {
-		let mut result: Vec<(usize, u64)> = Vec::new();
-		while self.items.len() > 0 {
-			self.num_inspections += 1;
-			let mut item = self.items.pop_front().unwrap();
-			let a = match self.operation[0] {
-				"old" => item,
-				val => val.parse::<u64>().expect("Error parsing a"),
-			};
+impl Worry for u64 {
+	fn from_u64(value: u64) -> u64 { value }
 
-			let b = match self.operation[2] {
-				"old" => item,
-				val => val.parse::<u64>().expect("Error parsing b"),
-			};
+	fn try_add(&self, other: &u64) -> Option<u64> { self.checked_add(*other) }
+
+	fn try_sub(&self, other: &u64) -> Option<u64> { self.checked_sub(*other) }
+
+	fn try_mul(&self, other: &u64) -> Option<u64> { self.checked_mul(*other) }
+
+	fn try_div(&self, other: &u64) -> Option<u64> { self.checked_div(*other) }
+
+	fn divided_by(&self, divisor: u64) -> u64 { self / divisor }
+
+	fn remainder(&self, modulus: u64) -> u64 { self % modulus }
+}
+
+impl Worry for BigUint {
+	fn from_u64(value: u64) -> BigUint { BigUint::from(value) }
+
+	fn try_add(&self, other: &BigUint) -> Option<BigUint> { Some(self + other) }
+
+	fn try_sub(&self, other: &BigUint) -> Option<BigUint> { if self >= other { Some(self - other) } else { None } }
+
+	fn try_mul(&self, other: &BigUint) -> Option<BigUint> { Some(self * other) }
+
+	fn try_div(&self, other: &BigUint) -> Option<BigUint> { if *other == BigUint::ZERO { None } else { Some(self / other) } }
+
+	fn divided_by(&self, divisor: u64) -> BigUint { self / divisor }
+
+	fn remainder(&self, modulus: u64) -> u64 { (self % modulus).iter_u64_digits().next().unwrap_or(0) }
+}
+
+///An arithmetic operator of a monkey's operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+	Add,
+	Subtract,
+	Multiply,
+	Divide,
+}
+
+impl BinaryOp {
+	fn symbol(&self) -> char {
+		return match self {
+			BinaryOp::Add => '+',
+			BinaryOp::Subtract => '-',
+			BinaryOp::Multiply => '*',
+			BinaryOp::Divide => '/',
+		}
+	}
+
+	//operators with a higher precedence are applied first
+	fn precedence(&self) -> u8 {
+		return match self {
+			BinaryOp::Add | BinaryOp::Subtract => 0,
+			BinaryOp::Multiply | BinaryOp::Divide => 1,
+		}
+	}
+}
+
+///Why evaluating an operation failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+	Overflow,
+	Underflow,
+	DivisionByZero,
+}
+
+impl fmt::Display for ArithmeticError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ArithmeticError::Overflow => write!(f, "worry level overflowed"),
+			ArithmeticError::Underflow => write!(f, "worry level went below zero"),
+			ArithmeticError::DivisionByZero => write!(f, "division by zero"),
+		}
+	}
+}
+
+impl std::error::Error for ArithmeticError {}
+
+///Errors from parsing an operation.  Positions are 0-based character offsets into the expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpressionError {
+	UnexpectedCharacter { position: usize, character: char },
+	UnexpectedToken { position: usize, token: String },
+	UnexpectedEnd,
+	InvalidNumber { position: usize, number: String },
+}
+
+impl fmt::Display for ExpressionError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ExpressionError::UnexpectedCharacter { position, character } => write!(f, "unexpected character '{}' at {}", character, position),
+			ExpressionError::UnexpectedToken { position, token } => write!(f, "unexpected {} at {}", token, position),
+			ExpressionError::UnexpectedEnd => write!(f, "unexpected end of expression"),
+			ExpressionError::InvalidNumber { position, number } => write!(f, "invalid number {} at {}", number, position),
+		}
+	}
+}
+
+impl std::error::Error for ExpressionError {}
+
+///The right hand side of a monkey's operation, such as "old * (old + 3) - 2", parsed once into a tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+	Old,
+	Number(u64),
+	Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
+}
+
+//a token of an expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+	Old,
+	Number(u64),
+	Op(BinaryOp),
+	Open,
+	Close,
+}
 
-			match self.operation[1] {
-				"+" => item = (a + b) % test_multiplied,
-				"*" => item = (a * b) % test_multiplied,
-				error => panic!("Unknown operation: {}", error),
+impl Token {
+	fn describe(&self) -> String {
+		return match self {
+			Token::Old => "old".to_string(),
+			Token::Number(number) => number.to_string(),
+			Token::Op(op) => format!("'{}'", op.symbol()),
+			Token::Open => "'('".to_string(),
+			Token::Close => "')'".to_string(),
+		}
+	}
+}
+
+fn tokenize(expression: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+	let chars: Vec<char> = expression.chars().collect();
+	let mut tokens = Vec::new();
+	let mut i = 0;
+	while i < chars.len() {
+		let start = i;
+		let token = match chars[i] {
+			c if c.is_whitespace() => { i += 1; continue; }
+			'+' => Token::Op(BinaryOp::Add),
+			'-' => Token::Op(BinaryOp::Subtract),
+			'*' => Token::Op(BinaryOp::Multiply),
+			'/' => Token::Op(BinaryOp::Divide),
+			'(' => Token::Open,
+			')' => Token::Close,
+			c if c.is_ascii_alphanumeric() => {
+				while i + 1 < chars.len() && chars[i + 1].is_ascii_alphanumeric() { i += 1; }
+				let word: String = chars[start..=i].iter().collect();
+				if word == "old" { Token::Old }
+				else { Token::Number(word.parse().map_err(|_| ExpressionError::InvalidNumber { position: start, number: word })?) }
 			}
+			character => return Err(ExpressionError::UnexpectedCharacter { position: start, character }),
+		};
+
+		tokens.push((start, token));
+		i += 1;
+	}
+
+	return Ok(tokens);
+}
+
+//a recursive descent parser over the tokens of an expression
+struct Parser {
+	tokens: Vec<(usize, Token)>,
+	next: usize,  // the index of the next token to read
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> { self.tokens.get(self.next).map(|(_, token)| token) }
+
+	fn unexpected(&self) -> ExpressionError {
+		return match self.tokens.get(self.next) {
+			Some((position, token)) => ExpressionError::UnexpectedToken { position: *position, token: token.describe() },
+			None => ExpressionError::UnexpectedEnd,
+		}
+	}
+
+	//parses operands joined by operators of at least the given precedence
+	fn binary(&mut self, precedence: u8) -> Result<Expr, ExpressionError> {
+		if precedence > 1 { return self.operand(); }
 
-			if item % self.test == 0 { result.push((self.test_pass, item)); }
-			else { result.push((self.test_fail, item)); }
+		let mut left = self.binary(precedence + 1)?;
+		while let Some(&Token::Op(op)) = self.peek() {
+			if op.precedence() != precedence { break; }
+
+			self.next += 1;
+			let right = self.binary(precedence + 1)?;
+			left = Expr::Binary { op, left: Box::new(left), right: Box::new(right) };
 		}
 
-		return result;
+		return Ok(left);
+	}
+
+	fn operand(&mut self) -> Result<Expr, ExpressionError> {
+		let expr = match self.peek() {
+			Some(Token::Old) => Expr::Old,
+			Some(&Token::Number(number)) => Expr::Number(number),
+			Some(Token::Open) => {
+				self.next += 1;
+				let inner = self.binary(0)?;
+				if self.peek() != Some(&Token::Close) { return Err(self.unexpected()); }
+
+				inner
+			}
+			_ => return Err(self.unexpected()),
+		};
+
+		self.next += 1;
+		return Ok(expr);
 	}
 }
 
-///The input is a file containing a list of monkeys.  After creating the monkeys, the program
-///will iterate through the monkeys and perform the operation on each monkey's items.  After 20
-///iterations, the program will output the product of the number of times the top two monkeys have
-///operated on an item.
-pub fn get_monkey_business(filename: &str) -> u64 {
-	let lines = get_lines(filename);
+impl FromStr for Expr {
+	type Err = ExpressionError;
+
+	///Parses an expression made of "old", whole numbers, the operators + - * / and parentheses.
+	///Operators of the same precedence are applied from left to right.
+	fn from_str(expression: &str) -> Result<Expr, ExpressionError> {
+		let mut parser = Parser { tokens: tokenize(expression)?, next: 0 };
+		let expr = parser.binary(0)?;
+		if parser.next < parser.tokens.len() { return Err(parser.unexpected()); }
+
+		return Ok(expr);
+	}
+}
+
+impl fmt::Display for Expr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Expr::Old => write!(f, "old"),
+			Expr::Number(number) => write!(f, "{}", number),
+			Expr::Binary { op, left, right } => {
+				//the right operand also needs parentheses at the same precedence, since "a - (b - c)" is not "a - b - c"
+				let left_parens = matches!(left.as_ref(), Expr::Binary { op: inner, .. } if inner.precedence() < op.precedence());
+				let right_parens = matches!(right.as_ref(), Expr::Binary { op: inner, .. } if inner.precedence() <= op.precedence());
+				if left_parens { write!(f, "({})", left)?; } else { write!(f, "{}", left)?; }
+				write!(f, " {} ", op.symbol())?;
+				if right_parens { write!(f, "({})", right) } else { write!(f, "{}", right) }
+			}
+		}
+	}
+}
+
+impl Expr {
+	///Evaluates the expression with `old` standing for the given worry level.
+	pub fn evaluate<W: Worry>(&self, old: &W) -> Result<W, ArithmeticError> {
+		return match self {
+			Expr::Old => Ok(old.clone()),
+			Expr::Number(number) => Ok(W::from_u64(*number)),
+			Expr::Binary { op, left, right } => {
+				let (left, right) = (left.evaluate(old)?, right.evaluate(old)?);
+				match op {
+					BinaryOp::Add => left.try_add(&right).ok_or(ArithmeticError::Overflow),
+					BinaryOp::Subtract => left.try_sub(&right).ok_or(ArithmeticError::Underflow),
+					BinaryOp::Multiply => left.try_mul(&right).ok_or(ArithmeticError::Overflow),
+					BinaryOp::Divide => left.try_div(&right).ok_or(ArithmeticError::DivisionByZero),
+				}
+			}
+		}
+	}
+
+	///Returns whether the expression only adds and multiplies, so that reducing `old` modulo
+	///some number and then evaluating gives the same remainder as evaluating first.
+	pub fn is_modular(&self) -> bool {
+		return match self {
+			Expr::Old | Expr::Number(_) => true,
+			Expr::Binary { op, left, right } => matches!(op, BinaryOp::Add | BinaryOp::Multiply) && left.is_modular() && right.is_modular(),
+		}
+	}
+}
+
+///How worry levels are kept in check after each inspection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relief {
+	///Divides the worry level by the given number, rounding down.
+	DivideBy(u64),
+	///Reduces the worry level modulo the least common multiple of the monkeys' divisors,
+	///which keeps it small without changing where any item is thrown.  Only works when
+	///every operation just adds and multiplies.
+	ModuloLcm,
+	///Lets the worry level grow without bound, using big integers.
+	None,
+}
+
+///The rules of a monkey business simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulationConfig {
+	pub rounds: u64,
+	pub relief: Relief,
+	pub top_k: usize,  // the number of most active monkeys whose inspection counts are multiplied together
+}
+
+impl SimulationConfig {
+	///The rules of the first part of the puzzle: 20 rounds, with worry levels divided by 3.
+	pub const PART_ONE: SimulationConfig = SimulationConfig { rounds: 20, relief: Relief::DivideBy(3), top_k: 2 };
+
+	///The rules of the second part of the puzzle: 10000 rounds, with worry levels only kept small
+	///by reducing them modulo the least common multiple of the divisors.
+	pub const PART_TWO: SimulationConfig = SimulationConfig { rounds: 10000, relief: Relief::ModuloLcm, top_k: 2 };
+
+	pub fn new(rounds: u64, relief: Relief, top_k: usize) -> SimulationConfig { SimulationConfig { rounds, relief, top_k } }
+}
+
+///Errors from running a simulation.  Rounds and monkeys are 0-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimulationError {
+	Arithmetic { round: u64, monkey: usize, error: ArithmeticError },
+	///The monkey's operation subtracts or divides, which reducing modulo the divisors does not preserve.
+	NotModular { monkey: usize },
+	DivideByZero,
+}
+
+impl fmt::Display for SimulationError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SimulationError::Arithmetic { round, monkey, error } => write!(f, "round {}, monkey {}: {}", round, monkey, error),
+			SimulationError::NotModular { monkey } => write!(f, "the operation of monkey {} cannot be reduced modulo the divisors", monkey),
+			SimulationError::DivideByZero => write!(f, "cannot divide worry levels by 0"),
+		}
+	}
+}
+
+impl std::error::Error for SimulationError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monkey {
+	items: VecDeque<u64>,       // the number assigned to each item the monkey holds
+	operation: Expr,            // the operation performed on each item per iteration
+	test: u64,                  // if the item value after the operation is divisible by this number, the test passes
+	test_pass: usize,           // what to do if the test passes
+	test_fail: usize,           // what to do if the test fails
+}
+
+impl Default for Monkey {
+	fn default() -> Monkey {
+		Monkey { items: VecDeque::new(), operation: Expr::Old, test: 1, test_pass: 0, test_fail: 0 }
+	}
+}
+
+impl Monkey {
+	pub fn operation(&self) -> &Expr { &self.operation }
+}
+
+///Reads the description of every monkey.
+pub fn parse_monkeys(lines: &[String]) -> Vec<Monkey> {
 	let mut monkeys: Vec<Monkey> = Vec::new();
-	let mut test_multiplied = 1; // the product of all the test numbers
-	for line in &lines {
+	for line in lines {
 		let stripped_line = line.trim();
 		let line_split: Vec<&str> = stripped_line.split(" ").collect();
 		match line_split[0] {
@@ -64,12 +384,11 @@ pub fn get_monkey_business(filename: &str) -> u64 {
 				}
 				monkeys.last_mut().unwrap().items = items;
 			}
-			"Operation:" => monkeys.last_mut().unwrap().operation = line_split[3..].to_vec(),
-			"Test:" => {
-				let test = line_split[3].parse::<u64>().expect("Error parsing test");
-				test_multiplied *= test;
-				monkeys.last_mut().unwrap().test = test;
+			"Operation:" => {
+				let expression = line_split[3..].join(" ");
+				monkeys.last_mut().unwrap().operation = expression.parse().unwrap_or_else(|error| panic!("Invalid operation: {error}"));
 			}
+			"Test:" => monkeys.last_mut().unwrap().test = line_split[3].parse::<u64>().expect("Error parsing test"),
 			"If" => {
 				match line_split[1] {
 					"true:" => monkeys.last_mut().unwrap().test_pass = line_split[5].parse::<usize>().expect("Error parsing test_pass"),
@@ -82,23 +401,271 @@ pub fn get_monkey_business(filename: &str) -> u64 {
 		}
 	}
 
-	for _ in 0..10000 {
-		for i in 0..monkeys.len() {
-			let result = monkeys[i].perform_operation(test_multiplied);
-			for (monkey_index, item) in result { monkeys[monkey_index].items.push_back(item); }
+	return monkeys;
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+	return if b == 0 { a } else { gcd(b, a % b) }
+}
+
+//the least common multiple of the monkeys' divisors
+fn divisor_lcm(monkeys: &[Monkey]) -> u64 {
+	return monkeys.iter().fold(1, |lcm, monkey| lcm / gcd(lcm, monkey.test) * monkey.test);
+}
+
+impl Monkey {
+	//inspects an item and applies relief, returning its new worry level and the monkey it is thrown to
+	fn inspect<W: Worry>(&self, item: &W, relief: Relief, lcm: u64) -> Result<(W, usize), ArithmeticError> {
+		let mut item = self.operation.evaluate(item)?;
+		item = match relief {
+			Relief::DivideBy(divisor) => item.divided_by(divisor),
+			Relief::ModuloLcm => W::from_u64(item.remainder(lcm)),
+			Relief::None => item,
+		};
+
+		let target = if item.remainder(self.test) == 0 { self.test_pass } else { self.test_fail };
+		return Ok((item, target));
+	}
+}
+
+///Identifies an item by its position in the starting items, counting through the monkeys in order.
+pub type ItemId = usize;
+
+///A single inspection of an item.  Rounds are 0-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Throw<W> {
+	pub round: u64,
+	pub monkey: usize,  // the monkey that inspected the item
+	pub worry: W,       // the worry level after the inspection and relief
+	pub target: usize,  // the monkey the item was thrown to
+}
+
+///Everything that happened to an item during a simulation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemTrace<W> {
+	pub id: ItemId,
+	pub start: usize,  // the monkey holding the item before the first round
+	pub path: Vec<Throw<W>>,
+}
+
+impl<W> ItemTrace<W> {
+	pub fn inspection_count(&self) -> u64 { self.path.len() as u64 }
+
+	///Returns how many times the given monkey inspected the item.
+	pub fn inspections_by(&self, monkey: usize) -> u64 { self.path.iter().filter(|throw| throw.monkey == monkey).count() as u64 }
+}
+
+//runs the simulation with worry levels of type W, recording every throw if traces are given
+fn simulate_with<W: Worry>(monkeys: &[Monkey], config: &SimulationConfig, mut traces: Option<&mut Vec<ItemTrace<W>>>) -> Result<Vec<u64>, SimulationError> {
+	let lcm = divisor_lcm(monkeys);
+	let mut items: Vec<VecDeque<(ItemId, W)>> = vec![VecDeque::new(); monkeys.len()];
+	for (i, monkey) in monkeys.iter().enumerate() {
+		for &item in &monkey.items {
+			let id = items.iter().map(|held| held.len()).sum();
+			if let Some(traces) = traces.as_mut() { traces.push(ItemTrace { id, start: i, path: Vec::new() }); }
+			items[i].push_back((id, W::from_u64(item)));
 		}
 	}
 
-	let mut greatest = 0;
-	let mut second_greatest = 0;
-	for monkey in &monkeys {
-		if monkey.num_inspections > greatest {
-			second_greatest = greatest;
-			greatest = monkey.num_inspections;
-		} else if monkey.num_inspections > second_greatest {
-			second_greatest = monkey.num_inspections;
+	let mut inspections = vec![0; monkeys.len()];
+	for round in 0..config.rounds {
+		for (i, monkey) in monkeys.iter().enumerate() {
+			//items thrown during the turn, even back to the same monkey, wait for its next turn
+			for (id, item) in std::mem::take(&mut items[i]) {
+				inspections[i] += 1;
+				let (item, target) = monkey.inspect(&item, config.relief, lcm).map_err(|error| SimulationError::Arithmetic { round, monkey: i, error })?;
+				if let Some(traces) = traces.as_mut() {
+					traces[id].path.push(Throw { round, monkey: i, worry: item.clone(), target });
+				}
+
+				items[target].push_back((id, item));
+			}
 		}
 	}
 
-	return greatest * second_greatest;
-}
\ No newline at end of file
+	return Ok(inspections);
+}
+
+//checks that the simulation can run with the given relief
+fn check_relief(monkeys: &[Monkey], relief: Relief) -> Result<(), SimulationError> {
+	if relief == Relief::DivideBy(0) { return Err(SimulationError::DivideByZero); }
+	if relief == Relief::ModuloLcm {
+		if let Some(monkey) = monkeys.iter().position(|monkey| !monkey.operation.is_modular()) { return Err(SimulationError::NotModular { monkey }); }
+	}
+
+	return Ok(());
+}
+
+///Runs the monkeys for the configured number of rounds and returns how many items each monkey inspected.
+///Worry levels are 64 bit integers, except without relief, where they are big integers.
+pub fn simulate(monkeys: &[Monkey], config: &SimulationConfig) -> Result<Vec<u64>, SimulationError> {
+	check_relief(monkeys, config.relief)?;
+
+	return match config.relief {
+		Relief::None => simulate_with::<BigUint>(monkeys, config, None),
+		_ => simulate_with::<u64>(monkeys, config, None),
+	}
+}
+
+///Runs the monkeys like `simulate`, and also records every inspection of every item.
+///Worry levels are of type W, so big integers are needed to trace without relief.
+pub fn trace_items<W: Worry>(monkeys: &[Monkey], config: &SimulationConfig) -> Result<(Vec<u64>, Vec<ItemTrace<W>>), SimulationError> {
+	check_relief(monkeys, config.relief)?;
+
+	let mut traces = Vec::new();
+	let inspections = simulate_with(monkeys, config, Some(&mut traces))?;
+	return Ok((inspections, traces));
+}
+
+///The inspections of an item under modulo relief, which repeat forever once the item is back
+///at the same monkey with the same worry level.  Rounds are 0-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemCycle {
+	pub id: ItemId,
+	pub visits: Vec<(u64, usize)>,  // the round and monkey of every inspection until the first repeat
+	pub cycle_start: usize,         // the index in `visits` of the first inspection that repeats
+	pub period: u64,                // the number of rounds between repeats
+}
+
+impl ItemCycle {
+	///Returns how many times each monkey inspects the item within the given number of rounds.
+	pub fn inspections(&self, rounds: u64, monkey_count: usize) -> Vec<u64> {
+		let mut inspections = vec![0; monkey_count];
+		for (index, &(round, monkey)) in self.visits.iter().enumerate() {
+			if round >= rounds { continue; }
+
+			inspections[monkey] += if index < self.cycle_start { 1 } else { (rounds - 1 - round) / self.period + 1 };
+		}
+
+		return inspections;
+	}
+}
+
+///Follows every item on its own until it repeats a monkey and worry level, with worry levels
+///reduced modulo the divisors.  Items never affect each other, an item thrown to a later monkey
+///is just inspected again in the same round, and one thrown to an earlier monkey or back to the
+///same one in the next, the same as in `simulate`.
+pub fn find_item_cycles(monkeys: &[Monkey]) -> Result<Vec<ItemCycle>, SimulationError> {
+	check_relief(monkeys, Relief::ModuloLcm)?;
+
+	let lcm = divisor_lcm(monkeys);
+	let mut cycles = Vec::new();
+	for (start, holder) in monkeys.iter().enumerate() {
+		for &item in &holder.items {
+			let mut seen: HashMap<(usize, u64), usize> = HashMap::new();  // the first visit with each monkey and worry level
+			let mut visits = Vec::new();
+			let (mut round, mut monkey, mut worry) = (0, start, item % lcm);
+			let cycle_start = loop {
+				if let Some(&first) = seen.get(&(monkey, worry)) { break first; }
+
+				seen.insert((monkey, worry), visits.len());
+				visits.push((round, monkey));
+				let (next, target) = monkeys[monkey].inspect(&worry, Relief::ModuloLcm, lcm).map_err(|error| SimulationError::Arithmetic { round, monkey, error })?;
+				if target <= monkey { round += 1; }
+				(monkey, worry) = (target, next);
+			};
+
+			let period = round - visits[cycle_start].0;
+			cycles.push(ItemCycle { id: cycles.len(), visits, cycle_start, period });
+		}
+	}
+
+	return Ok(cycles);
+}
+
+///Returns how many items each monkey inspects within the given number of rounds, with worry
+///levels reduced modulo the divisors, without simulating every round.
+pub fn extrapolate_inspections(monkeys: &[Monkey], rounds: u64) -> Result<Vec<u64>, SimulationError> {
+	let mut inspections = vec![0; monkeys.len()];
+	for cycle in find_item_cycles(monkeys)? {
+		for (total, count) in inspections.iter_mut().zip(cycle.inspections(rounds, monkeys.len())) { *total += count; }
+	}
+
+	return Ok(inspections);
+}
+
+///Returns the product of the `top_k` greatest inspection counts.
+pub fn monkey_business(inspections: &[u64], top_k: usize) -> u64 {
+	let mut sorted = inspections.to_vec();
+	sorted.sort_unstable_by(|a, b| b.cmp(a));
+
+	return sorted.iter().take(top_k).product();
+}
+
+///Simulates the monkeys in the file with the given rules and returns the level of monkey business.
+pub fn simulate_monkey_business(filename: &str, config: &SimulationConfig) -> u64 {
+	let lines = get_lines(filename);
+	let inspections = simulate(&parse_monkeys(&lines), config).unwrap_or_else(|error| panic!("{}", error));
+
+	return monkey_business(&inspections, config.top_k);
+}
+
+///The input is a file containing a list of monkeys.  After each inspection, the worry level of the
+///item is divided by 3.  After 20 rounds, the program will output the product of the number of
+///times the top two monkeys have operated on an item.
+pub fn get_relieved_monkey_business(filename: &str) -> u64 {
+	return simulate_monkey_business(filename, &SimulationConfig::PART_ONE);
+}
+
+///The input is a file containing a list of monkeys.  After creating the monkeys, the program
+///will iterate through the monkeys and perform the operation on each monkey's items.  After 10000
+///iterations, the program will output the product of the number of times the top two monkeys have
+///operated on an item.
+pub fn get_monkey_business(filename: &str) -> u64 {
+	return simulate_monkey_business(filename, &SimulationConfig::PART_TWO);
+}
+
+///Like `get_monkey_business`, but for any number of rounds, such as 10^12, by following each
+///item until its inspections start repeating instead of simulating every round.  Multiplies the
+///inspection counts of the `top_k` most active monkeys.
+///The result is wider than the inspection counts, since their product overflows 64 bits for huge round counts.
+pub fn get_extrapolated_monkey_business(filename: &str, rounds: u64, top_k: usize) -> u128 {
+	let lines = get_lines(filename);
+	let mut inspections = extrapolate_inspections(&parse_monkeys(&lines), rounds).unwrap_or_else(|error| panic!("{}", error));
+	inspections.sort_unstable_by(|a, b| b.cmp(a));
+
+	return inspections.iter().take(top_k).map(|&count| count as u128).product();
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn monkeys(description: &str) -> Vec<Monkey> {
+		return parse_monkeys(&description.lines().map(str::to_string).collect::<Vec<String>>());
+	}
+
+	//checks that extrapolating agrees with simulating every round
+	fn assert_extrapolation_matches(monkeys: &[Monkey]) {
+		for rounds in [0, 1, 2, 3, 20, 500, 10000] {
+			let simulated = simulate(monkeys, &SimulationConfig::new(rounds, Relief::ModuloLcm, 2)).unwrap();
+			assert_eq!(extrapolate_inspections(monkeys, rounds).unwrap(), simulated, "after {} rounds", rounds);
+		}
+	}
+
+	#[test]
+	fn extrapolation_matches_simulation_with_throws_to_self() {
+		let monkeys = monkeys("Monkey 0:
+  Starting items: 2
+  Operation: new = old * 2
+  Test: divisible by 2
+    If true: throw to monkey 0
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items: 3
+  Operation: new = old + 1
+  Test: divisible by 5
+    If true: throw to monkey 0
+    If false: throw to monkey 1");
+
+		assert_eq!(simulate(&monkeys, &SimulationConfig::new(3, Relief::ModuloLcm, 2)).unwrap(), vec![4, 2]);
+		assert_extrapolation_matches(&monkeys);
+	}
+
+	#[test]
+	fn extrapolation_matches_simulation_on_puzzle_input() {
+		let lines = get_lines("input/p11.txt");
+		assert_extrapolation_matches(&parse_monkeys(&lines));
+	}
+}
@@ -1,5 +1,5 @@
 use std::cmp::Reverse;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use priority_queue::PriorityQueue;
 use crate::tasks::helper::get_lines;
 
@@ -13,23 +13,58 @@ fn get_elevation(c: char) -> u32 {
 	else { (c as u32) - ('a' as u32) }
 }
 
-fn get_valid_moves(map: &Vec<String>, pos: (i32, i32)) -> Vec<(i32, i32)> {
-	let mut moves: Vec<(i32, i32)> = Vec::new();
-	if pos.0 > 0 && get_elevation(map[pos.0 as usize - 1].chars().nth(pos.1 as usize).unwrap()) <= get_elevation(map[pos.0 as usize].chars().nth(pos.1 as usize).unwrap()) + 1
-	{ moves.push((pos.0 - 1, pos.1)); }
-	if pos.0 < map.len() as i32 - 1 && get_elevation(map[pos.0 as usize + 1].chars().nth(pos.1 as usize).unwrap()) <= get_elevation(map[pos.0 as usize].chars().nth(pos.1 as usize).unwrap()) + 1
-	{ moves.push((pos.0 + 1, pos.1)); }
-	if pos.1 > 0 && get_elevation(map[pos.0 as usize].chars().nth(pos.1 as usize - 1).unwrap()) <= get_elevation(map[pos.0 as usize].chars().nth(pos.1 as usize).unwrap()) + 1
-	{ moves.push((pos.0, pos.1 - 1)); }
-	if pos.1 < map[pos.0 as usize].len() as i32 - 1 && get_elevation(map[pos.0 as usize].chars().nth(pos.1 as usize + 1).unwrap()) <= get_elevation(map[pos.0 as usize].chars().nth(pos.1 as usize).unwrap()) + 1
-	{ moves.push((pos.0, pos.1 + 1)); }
-
-	return moves;
+///Limits on how much the elevation may change in a single step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClimbingRules {
+	pub max_ascent: u32,
+	pub max_descent: Option<u32>,  // None if any drop is allowed
 }
 
-///Given a map, the starting position, and the final position, this function returns the length
-///of the shortest path from the starting position to the final position using A*.
-fn path_len(map: &Vec<String>, start_pos: (i32, i32), final_pos: (i32, i32)) -> Option<u32> {
+impl Default for ClimbingRules {
+	///The rules of the puzzle: climb at most 1 unit, and drop any amount.
+	fn default() -> ClimbingRules { ClimbingRules { max_ascent: 1, max_descent: None } }
+}
+
+impl ClimbingRules {
+	pub fn new(max_ascent: u32, max_descent: Option<u32>) -> ClimbingRules { ClimbingRules { max_ascent, max_descent } }
+
+	///Returns whether a step from the first elevation to the second is allowed.
+	pub fn can_step(&self, from: u32, to: u32) -> bool {
+		return if to >= from { to - from <= self.max_ascent }
+		else { self.max_descent.is_none_or(|max_descent| from - to <= max_descent) }
+	}
+}
+
+fn get_valid_moves(map: &[String], pos: (i32, i32), rules: &ClimbingRules) -> Vec<(i32, i32)> {
+	let elevation_at = |(i, j): (i32, i32)| {
+		if i < 0 || j < 0 { return None; }
+		return map.get(i as usize).and_then(|line| line.chars().nth(j as usize)).map(get_elevation);
+	};
+
+	let current = elevation_at(pos).unwrap();
+	return [(pos.0 - 1, pos.1), (pos.0 + 1, pos.1), (pos.0, pos.1 - 1), (pos.0, pos.1 + 1)].into_iter()
+		.filter(|&next| elevation_at(next).is_some_and(|elevation| rules.can_step(current, elevation)))
+		.collect();
+}
+
+//finds the positions of 'S' and 'E' on the map
+fn find_start_and_end(map: &[String]) -> ((i32, i32), (i32, i32)) {
+	let mut start_pos: (i32, i32) = (0, 0);
+	let mut final_pos: (i32, i32) = (0, 0);
+	for (i, line) in map.iter().enumerate() {
+		for (j, c) in line.chars().enumerate() {
+			if c == 'S' { start_pos = (i as i32, j as i32); }
+			else if c == 'E' { final_pos = (i as i32, j as i32); }
+		}
+	}
+
+	return (start_pos, final_pos);
+}
+
+///Given a map, the starting position, the final position and the climbing rules, this function
+///returns every position along the shortest path from the starting position to the final position,
+///both included, using A*.  Returns None if the final position cannot be reached.
+pub fn find_path(map: &[String], start_pos: (i32, i32), final_pos: (i32, i32), rules: &ClimbingRules) -> Option<Vec<(i32, i32)>> {
 	// stores all possible moves and their manhattan distances from the final position
 	let mut possible_moves: PriorityQueue<(i32, i32), Reverse<i32>> = PriorityQueue::new();
 
@@ -42,11 +77,11 @@ fn path_len(map: &Vec<String>, start_pos: (i32, i32), final_pos: (i32, i32)) ->
 	possible_moves.push(start_pos, Reverse(get_manhattan_distance(start_pos, final_pos)));
 	distance_map.insert(start_pos, 0);
 	parents.insert(start_pos, start_pos);
-	while possible_moves.len() > 0 {
+	while !possible_moves.is_empty() {
 		let current_pos = possible_moves.pop().unwrap().0;
 		if current_pos == final_pos { break; }
 
-		for next_pos in get_valid_moves(map, current_pos) {
+		for next_pos in get_valid_moves(map, current_pos, rules) {
 			let next_distance = distance_map[&current_pos] + 1;
 			if next_distance < *distance_map.get(&next_pos).unwrap_or(&i32::MAX) {
 				distance_map.insert(next_pos, next_distance);
@@ -56,8 +91,32 @@ fn path_len(map: &Vec<String>, start_pos: (i32, i32), final_pos: (i32, i32)) ->
 		}
 	}
 
-	return if !distance_map.contains_key(&final_pos) { None }
-	else { Some(distance_map[&final_pos] as u32) }
+	if !parents.contains_key(&final_pos) { return None; }
+
+	let mut path = vec![final_pos];
+	while *path.last().unwrap() != start_pos { path.push(parents[path.last().unwrap()]); }
+	path.reverse();
+
+	return Some(path);
+}
+
+///Draws the map with the path on it, like the puzzle does.  Each square of the path shows an arrow
+///pointing to the next square, the final square is marked with an 'E', and every other square is a '.'.
+pub fn render_path(map: &[String], path: &[(i32, i32)]) -> String {
+	let mut canvas: Vec<Vec<char>> = map.iter().map(|line| vec!['.'; line.chars().count()]).collect();
+	for step in path.windows(2) {
+		let ((i, j), (next_i, next_j)) = (step[0], step[1]);
+		canvas[i as usize][j as usize] = match (next_i - i, next_j - j) {
+			(-1, 0) => '^',
+			(1, 0) => 'v',
+			(0, -1) => '<',
+			_ => '>',
+		};
+	}
+
+	if let Some(&(i, j)) = path.last() { canvas[i as usize][j as usize] = 'E'; }
+
+	return canvas.iter().map(|row| row.iter().collect::<String>() + "\n").collect();
 }
 
 ///The input is a file containing an elevation map of a region.
@@ -68,38 +127,65 @@ fn path_len(map: &Vec<String>, start_pos: (i32, i32), final_pos: (i32, i32)) ->
 ///the elevation must be at most 1 unit higher than the current square.
 ///this function returns the length of the shortest path from 'S' to 'E'.
 pub fn get_shortest_path_len(filename: &str) -> u32 {
+	return get_shortest_path(filename).len() as u32 - 1;
+}
+
+///Same as the above function, but returns every position along the path from 'S' to 'E' as (row, column).
+pub fn get_shortest_path(filename: &str) -> Vec<(i32, i32)> {
 	let map = get_lines(filename);
-	let mut start_pos: (i32, i32) = (0, 0);
-	let mut final_pos: (i32, i32) = (0, 0);
-	'outer: for i in 0..map.len() {
-		for j in 0..map[i].len() {
-			if map[i].chars().nth(j).unwrap() == 'S' { start_pos = (i as i32, j as i32); }
-			else if map[i].chars().nth(j).unwrap() == 'E' { final_pos = (i as i32, j as i32); }
+	let (start_pos, final_pos) = find_start_and_end(&map);
 
-			if start_pos != (0, 0) && final_pos != (0, 0) { break 'outer; }
+	return find_path(&map, start_pos, final_pos, &ClimbingRules::default()).expect("No path from S to E");
+}
+
+///Draws the shortest path from 'S' to 'E' over the map with arrows.
+pub fn render_shortest_path(filename: &str) -> String {
+	let map = get_lines(filename);
+	let (start_pos, final_pos) = find_start_and_end(&map);
+	let path = find_path(&map, start_pos, final_pos, &ClimbingRules::default()).expect("No path from S to E");
+
+	return render_path(&map, &path);
+}
+
+///Returns the fewest steps from every position of the map to the final position, or None for
+///positions that cannot reach it.  Rather than searching from every position, this runs a single
+///breadth first search backwards from the final position, where stepping from a square to a
+///neighbour is allowed if the rules would allow stepping from the neighbour onto the square.
+pub fn distances_to(map: &[String], final_pos: (i32, i32), rules: &ClimbingRules) -> Vec<Vec<Option<u32>>> {
+	let elevations: Vec<Vec<u32>> = map.iter().map(|line| line.chars().map(get_elevation).collect()).collect();
+	let mut distances: Vec<Vec<Option<u32>>> = elevations.iter().map(|row| vec![None; row.len()]).collect();
+	let mut queue: VecDeque<(usize, usize)> = VecDeque::new();
+
+	distances[final_pos.0 as usize][final_pos.1 as usize] = Some(0);
+	queue.push_back((final_pos.0 as usize, final_pos.1 as usize));
+	while let Some((i, j)) = queue.pop_front() {
+		let distance = distances[i][j].unwrap();
+		let neighbours = [(i.wrapping_sub(1), j), (i + 1, j), (i, j.wrapping_sub(1)), (i, j + 1)];
+		for (next_i, next_j) in neighbours {
+			let Some(&elevation) = elevations.get(next_i).and_then(|row| row.get(next_j)) else { continue };
+			if !rules.can_step(elevation, elevations[i][j]) || distances[next_i][next_j].is_some() { continue; }
+
+			distances[next_i][next_j] = Some(distance + 1);
+			queue.push_back((next_i, next_j));
 		}
 	}
 
-	return path_len(&map, start_pos, final_pos).unwrap();
+	return distances;
 }
 
+///Same as the above function, but this time the path can start from any square with
+///elevation 'a'.  This function returns the length of the shortest of those paths.
 pub fn get_fewest_steps(filename: &str) -> u32 {
 	let map = get_lines(filename);
-	let mut start_positions: Vec<(i32, i32)> = Vec::new();
+	let mut start_positions: Vec<(usize, usize)> = Vec::new();
 	let mut final_pos: (i32, i32) = (0, 0);
-	for i in 0..map.len() {
-		for j in 0..map[i].len() {
-			let c = map[i].chars().nth(j).unwrap();
-			if c == 'S' || c == 'a' { start_positions.push((i as i32, j as i32)); }
+	for (i, line) in map.iter().enumerate() {
+		for (j, c) in line.chars().enumerate() {
+			if c == 'S' || c == 'a' { start_positions.push((i, j)); }
 			else if c == 'E' { final_pos = (i as i32, j as i32); }
 		}
 	}
 
-	let mut shortest_path_len = u32::MAX;
-	for start_pos in start_positions {
-		let path_len = path_len(&map, start_pos, final_pos);
-		shortest_path_len = path_len.unwrap_or(u32::MAX).min(shortest_path_len);
-	}
-
-	return shortest_path_len;
-}
\ No newline at end of file
+	let distances = distances_to(&map, final_pos, &ClimbingRules::default());
+	return start_positions.iter().filter_map(|&(i, j)| distances[i][j]).min().unwrap_or(u32::MAX);
+}
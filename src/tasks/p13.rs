@@ -1,99 +1,371 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
 use crate::tasks::helper::get_lines;
 
-enum Packet {
+///Errors from parsing a packet.  Positions are 0-based character offsets into the line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacketError {
+	UnexpectedCharacter { position: usize, character: char },
+	UnexpectedEnd,
+	InvalidNumber { position: usize, number: String },
+}
+
+impl fmt::Display for PacketError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			PacketError::UnexpectedCharacter { position, character } => write!(f, "unexpected character '{}' at {}", character, position),
+			PacketError::UnexpectedEnd => write!(f, "unexpected end of packet"),
+			PacketError::InvalidNumber { position, number } => write!(f, "invalid number {} at {}", number, position),
+		}
+	}
+}
+
+impl std::error::Error for PacketError {}
+
+///A packet is either a number or a list of packets.
+///Packets are ordered the way the distress signal expects: numbers by value, lists element by
+///element and then by length, and a number compared with a list is treated as a list holding
+///just that number.  Packets that the signal puts in no particular order, such as 2 and [[2]],
+///are ordered by their structure, with numbers before lists, so that the order agrees with `==`,
+///which compares structure.  `signal_cmp` gives the order of the signal alone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
 	Number(i32),
 	Array(Vec<Packet>),
 }
 
+//compares two lists element by element and then by length, with the given comparison for elements
+fn compare_lists(a: &[Packet], o: &[Packet], compare: fn(&Packet, &Packet) -> Ordering) -> Ordering {
+	return a.iter().zip(o).map(|(a, o)| compare(a, o)).find(|ordering| ordering.is_ne()).unwrap_or_else(|| a.len().cmp(&o.len()));
+}
+
 impl Packet {
-	fn compare(&self, other: &Packet) -> std::cmp::Ordering {
+	///Compares two packets only the way the distress signal expects, so 2, [2] and [[2]] are all equal.
+	pub fn signal_cmp(&self, other: &Packet) -> Ordering {
+		return match (self, other) {
+			(Packet::Number(n), Packet::Number(o)) => n.cmp(o),
+			(Packet::Array(a), Packet::Array(o)) => compare_lists(a, o, Packet::signal_cmp),
+			(Packet::Number(_), Packet::Array(o)) => compare_lists(std::slice::from_ref(self), o, Packet::signal_cmp),
+			(Packet::Array(a), Packet::Number(_)) => compare_lists(a, std::slice::from_ref(other), Packet::signal_cmp),
+		}
+	}
+
+	//compares the structure of two packets, with numbers before lists
+	fn structure_cmp(&self, other: &Packet) -> Ordering {
+		return match (self, other) {
+			(Packet::Number(n), Packet::Number(o)) => n.cmp(o),
+			(Packet::Array(a), Packet::Array(o)) => compare_lists(a, o, Packet::structure_cmp),
+			(Packet::Number(_), Packet::Array(_)) => Ordering::Less,
+			(Packet::Array(_), Packet::Number(_)) => Ordering::Greater,
+		}
+	}
+}
+
+impl Ord for Packet {
+	fn cmp(&self, other: &Packet) -> Ordering {
+		return self.signal_cmp(other).then_with(|| self.structure_cmp(other));
+	}
+}
+
+impl PartialOrd for Packet {
+	fn partial_cmp(&self, other: &Packet) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl FromStr for Packet {
+	type Err = PacketError;
+
+	///Parses a packet such as "[1,[2,3],[]]" in a single pass over the characters, keeping the
+	///lists that are still open on a stack.  Whitespace between elements is ignored.
+	fn from_str(string: &str) -> Result<Packet, PacketError> {
+		let mut open: Vec<Vec<Packet>> = Vec::new();  // the elements read so far of every open list
+		let mut packet: Option<Packet> = None;           // the outermost packet, once it is complete
+		let mut expect_element = true;                    // whether an element must come next
+		let mut list_start = false;                       // whether a list was just opened, so it may close right away
+		let mut chars = string.char_indices().peekable();
+
+		while let Some((position, c)) = chars.next() {
+			if c.is_whitespace() { continue; }
+
+			let unexpected = PacketError::UnexpectedCharacter { position, character: c };
+			if packet.is_some() { return Err(unexpected); }
+
+			let element = match c {
+				'[' if expect_element => {
+					open.push(Vec::new());
+					list_start = true;
+					continue;
+				}
+				']' if (!expect_element || list_start) && !open.is_empty() => Packet::Array(open.pop().unwrap()),
+				',' if !expect_element && !open.is_empty() => {
+					expect_element = true;
+					continue;
+				}
+				'0'..='9' | '-' if expect_element => {
+					let mut number = c.to_string();
+					while let Some((_, digit)) = chars.next_if(|(_, c)| c.is_ascii_digit()) { number.push(digit); }
+
+					Packet::Number(number.parse().map_err(|_| PacketError::InvalidNumber { position, number: number.clone() })?)
+				}
+				_ => return Err(unexpected),
+			};
+
+			expect_element = false;
+			list_start = false;
+			match open.last_mut() {
+				Some(list) => list.push(element),
+				None => packet = Some(element),
+			}
+		}
+
+		return packet.ok_or(PacketError::UnexpectedEnd);
+	}
+}
+
+impl fmt::Display for Packet {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
-			Packet::Number(n) => match other {
-				Packet::Number(o) => n.cmp(o),
-				Packet::Array(_) => {
-					let mut array: Vec<Packet> = Vec::new();
-					array.push(Packet::Number(*n));
-
-					Packet::Array(array).compare(other)
-				},
-			},
-			Packet::Array(a) => match other {
-				Packet::Number(n) => {
-					let mut array: Vec<Packet> = Vec::new();
-					array.push(Packet::Number(*n));
-
-					self.compare(&Packet::Array(array))
-				},
-				Packet::Array(o) => {
-					let compare_length = a.len().min(o.len());
-					for i in 0..compare_length {
-						let result = a[i].compare(&o[i]);
-						if result != std::cmp::Ordering::Equal { return result; }
-					}
-
-					return if a.len() > o.len() { std::cmp::Ordering::Greater }
-					else if a.len() < o.len() { std::cmp::Ordering::Less }
-					else { std::cmp::Ordering::Equal }
+			Packet::Number(n) => write!(f, "{}", n),
+			Packet::Array(a) => {
+				write!(f, "[")?;
+				for (i, element) in a.iter().enumerate() {
+					if i > 0 { write!(f, ",")?; }
+					write!(f, "{}", element)?;
 				}
+
+				write!(f, "]")
 			}
 		}
 	}
+}
+
+///One of the two packets being compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+	Left,
+	Right,
+}
+
+impl fmt::Display for Side {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Side::Left => write!(f, "left"),
+			Side::Right => write!(f, "right"),
+		}
+	}
+}
+
+///What settled a comparison of two packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+	///The number on this side was smaller.
+	Smaller(Side),
+	///The list on this side ran out of items first.
+	RanOut(Side),
+}
+
+impl fmt::Display for Decision {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let (side, event) = match self {
+			Decision::Smaller(side) => (side, "is smaller"),
+			Decision::RanOut(side) => (side, "ran out of items"),
+		};
+
+		let (name, order) = match side {
+			Side::Left => ("Left", "in the right order"),
+			Side::Right => ("Right", "not in the right order"),
+		};
+
+		return write!(f, "{} side {}, so inputs are {}", name, event, order);
+	}
+}
+
+///A number that was wrapped in a list to be compared with a list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Promotion {
+	pub path: Vec<usize>,  // the indices leading from the outermost lists to the number
+	pub side: Side,
+}
 
-	fn from_string(string: &str) -> Packet {
-		if string.starts_with('[') {
-			if string.len() == 2 { return Packet::Array(Vec::new()); }
-
-			let mut array = Vec::new();
-			let mut current_value = String::new();
-			let mut current_array_depth = 0;
-			for c in string[1..string.len() - 1].chars() {
-				match c {
-					'[' => {
-						current_array_depth += 1;
-						current_value.push(c);
-					},
-					']' => {
-						current_array_depth -= 1;
-						current_value.push(c);
-					},
-					',' => {
-						if current_array_depth == 0 {
-							array.push(Packet::from_string(&current_value));
-							current_value.clear();
-						} else { current_value.push(c); }
-					},
-					_ => current_value.push(c),
+//a line of the explanation, indented by its depth
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+	Compare { depth: usize, left: Packet, right: Packet },
+	Promote { depth: usize, side: Side, promoted: Packet },
+	Decide { depth: usize, decision: Decision },
+}
+
+///How a comparison of two packets went.  Prints in the style of the puzzle walkthrough.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+	pub ordering: Ordering,
+	///The indices leading from the outermost lists to the elements that decided the order.  When a
+	///list ran out, the last index is the one that only the longer list has.  None if the packets are equal.
+	pub decided_at: Option<Vec<usize>>,
+	pub decision: Option<Decision>,
+	pub promotions: Vec<Promotion>,
+	steps: Vec<Step>,
+}
+
+impl Comparison {
+	//compares two packets at the given depth and path, recording every step
+	fn explain(&mut self, left: &Packet, right: &Packet, depth: usize, path: &mut Vec<usize>) -> Ordering {
+		self.steps.push(Step::Compare { depth, left: left.clone(), right: right.clone() });
+		let (decision, index) = match (left, right) {
+			(Packet::Number(l), Packet::Number(r)) => match l.cmp(r) {
+				Ordering::Less => (Decision::Smaller(Side::Left), None),
+				Ordering::Greater => (Decision::Smaller(Side::Right), None),
+				Ordering::Equal => return Ordering::Equal,
+			},
+			(Packet::Number(_), Packet::Array(_)) | (Packet::Array(_), Packet::Number(_)) => {
+				let side = if let Packet::Number(_) = left { Side::Left } else { Side::Right };
+				let promote = |packet: &Packet| if let Packet::Number(_) = packet { Packet::Array(vec![packet.clone()]) } else { packet.clone() };
+				let (left, right) = (promote(left), promote(right));
+
+				self.promotions.push(Promotion { path: path.clone(), side });
+				self.steps.push(Step::Promote { depth: depth + 1, side, promoted: if side == Side::Left { left.clone() } else { right.clone() } });
+				return self.explain(&left, &right, depth + 1, path);
+			}
+			(Packet::Array(l), Packet::Array(r)) => {
+				for (i, (l, r)) in l.iter().zip(r).enumerate() {
+					path.push(i);
+					let ordering = self.explain(l, r, depth + 1, path);
+					path.pop();
+					if ordering != Ordering::Equal { return ordering; }
+				}
+
+				match l.len().cmp(&r.len()) {
+					Ordering::Less => (Decision::RanOut(Side::Left), Some(l.len())),
+					Ordering::Greater => (Decision::RanOut(Side::Right), Some(r.len())),
+					Ordering::Equal => return Ordering::Equal,
 				}
 			}
+		};
+
+		let mut decided_at = path.clone();
+		decided_at.extend(index);
+		self.decided_at = Some(decided_at);
+		self.decision = Some(decision);
+		self.steps.push(Step::Decide { depth: depth + 1, decision });
 
-			array.push(Packet::from_string(&current_value));
-			return Packet::Array(array);
-		} else {
-			match string.parse() {
-				Ok(n) => return Packet::Number(n),
-				Err(_) => panic!("Invalid number: {}", string),
+		return match decision {
+			Decision::Smaller(Side::Left) | Decision::RanOut(Side::Left) => Ordering::Less,
+			_ => Ordering::Greater,
+		}
+	}
+}
+
+impl fmt::Display for Comparison {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for step in &self.steps {
+			match step {
+				Step::Compare { depth, left, right } => writeln!(f, "{:indent$}- Compare {} vs {}", "", left, right, indent = depth * 2)?,
+				Step::Promote { depth, side, promoted } =>
+					writeln!(f, "{:indent$}- Mixed types; convert {} to {} and retry comparison", "", side, promoted, indent = depth * 2)?,
+				Step::Decide { depth, decision } => writeln!(f, "{:indent$}- {}", "", decision, indent = depth * 2)?,
 			}
 		}
+
+		return Ok(());
 	}
+}
+
+impl Packet {
+	///Compares two packets like `signal_cmp`, and also explains how the order was decided.
+	pub fn explain(&self, other: &Packet) -> Comparison {
+		let mut comparison = Comparison { ordering: Ordering::Equal, decided_at: None, decision: None, promotions: Vec::new(), steps: Vec::new() };
+		comparison.ordering = comparison.explain(self, other, 0, &mut Vec::new());
+
+		return comparison;
+	}
+}
+
+///Errors from reading packets from a stream.  Lines are 1-based.
+#[derive(Debug)]
+pub enum ReadError {
+	Io(io::Error),
+	Packet { line: usize, error: PacketError },
+}
 
-	fn to_string(&self) -> String {
+impl fmt::Display for ReadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
-			Packet::Number(n) => n.to_string(),
-			Packet::Array(a) => {
-				let mut string = String::new();
-				string.push('[');
-				for element in a {
-					string.push_str(&element.to_string());
-					string.push(',');
-				}
+			ReadError::Io(error) => write!(f, "{}", error),
+			ReadError::Packet { line, error } => write!(f, "line {}: {}", line, error),
+		}
+	}
+}
 
-				if string.len() > 1 { string.pop(); }
+impl std::error::Error for ReadError {}
 
-				string.push(']');
-				string
-			},
+impl From<io::Error> for ReadError {
+	fn from(error: io::Error) -> ReadError { ReadError::Io(error) }
+}
+
+///Finds where divider packets end up among other packets without sorting them, by counting for
+///each divider how many packets come before it.  Each packet is compared once with each divider,
+///so packets can be fed in one at a time and never need to be kept.
+pub struct DividerCounter {
+	dividers: Vec<Packet>,
+	before: Vec<usize>,  // the number of packets pushed so far that come before each divider
+}
+
+impl DividerCounter {
+	pub fn new(dividers: Vec<Packet>) -> DividerCounter {
+		let before = vec![0; dividers.len()];
+		DividerCounter { dividers, before }
+	}
+
+	pub fn push(&mut self, packet: &Packet) {
+		for (divider, before) in self.dividers.iter().zip(&mut self.before) {
+			if packet <= divider { *before += 1; }
 		}
 	}
+
+	///Returns the 1-based position of each divider if the packets and dividers were sorted together,
+	///with the dividers added after the packets in order and a stable sort, so a packet equal to a
+	///divider comes before it.
+	pub fn indices(&self) -> Vec<usize> {
+		return self.dividers.iter().enumerate().map(|(i, divider)| {
+			let other_dividers = self.dividers.iter().enumerate()
+				.filter(|&(j, other)| if j < i { other <= divider } else { other < divider })
+				.count();
+
+			self.before[i] + other_dividers + 1
+		}).collect();
+	}
+}
+
+///Returns the 1-based position each divider would have if the packets and dividers were sorted together.
+pub fn divider_indices(packets: &[Packet], dividers: &[Packet]) -> Vec<usize> {
+	let mut counter = DividerCounter::new(dividers.to_vec());
+	for packet in packets { counter.push(packet); }
+
+	return counter.indices();
+}
+
+///Same as `divider_indices`, but reads the packets one line at a time, skipping blank lines,
+///so they never all have to be in memory.
+pub fn divider_indices_from_reader<R: BufRead>(reader: R, dividers: &[Packet]) -> Result<Vec<usize>, ReadError> {
+	let mut counter = DividerCounter::new(dividers.to_vec());
+	for (index, line) in reader.lines().enumerate() {
+		let line = line?;
+		if line.trim().is_empty() { continue; }
+
+		counter.push(&line.parse().map_err(|error| ReadError::Packet { line: index + 1, error })?);
+	}
+
+	return Ok(counter.indices());
+}
+
+//reads every packet in the file, skipping blank lines
+fn read_packets(filename: &str) -> Vec<Packet> {
+	return get_lines(filename).iter().enumerate()
+		.filter(|(_, line)| !line.is_empty())
+		.map(|(index, line)| line.parse().unwrap_or_else(|error| panic!("line {}: {}", index + 1, error)))
+		.collect();
 }
 
 ///The input is a file containing several pairs of arrays.
@@ -102,20 +374,11 @@ impl Packet {
 ///This function returns the sum of all indices of packets
 ///that are in the correct order.
 pub fn get_sum_correct_indices(filename: &str) -> u32 {
-	let lines = get_lines(filename);
-	let mut packets: Vec<Packet> = Vec::new();
-	for line in &lines {
-		if line.len() == 0 { continue; }
-
-		packets.push(Packet::from_string(line));
-	}
+	let packets = read_packets(filename);
 
-	let mut index = 1;
 	let mut sum = 0;
-	for i in (0..packets.len() - 1).step_by(2) {
-		let result = packets[i].compare(&packets[i + 1]);
-		if result != std::cmp::Ordering::Greater { sum += index; }
-		index += 1;
+	for (index, pair) in packets.chunks_exact(2).enumerate() {
+		if pair[0].signal_cmp(&pair[1]) != Ordering::Greater { sum += index as u32 + 1; }
 	}
 
 	sum
@@ -123,29 +386,21 @@ pub fn get_sum_correct_indices(filename: &str) -> u32 {
 
 ///The input is the same as described as before, but now all packets must be sorted.
 ///Additionally, two extra packets: [[2]] and [[6]] are added to the list of packets.
-///This function returns the product of the indices of the two new packets after sorting.
+///This function returns the product of the indices of the two new packets after sorting,
+///which are found by counting the packets that come before each of them rather than sorting.
 pub fn sort_packets(filename: &str) -> u32 {
-	let lines = get_lines(filename);
-	let mut packets: Vec<Packet> = Vec::new();
-	for line in &lines {
-		if line.len() == 0 { continue; }
+	let file = File::open(filename).expect("no such file");
+	let dividers: Vec<Packet> = ["[[2]]", "[[6]]"].iter().map(|divider| divider.parse().unwrap()).collect();
+	let indices = divider_indices_from_reader(BufReader::new(file), &dividers).unwrap_or_else(|error| panic!("{}", error));
 
-		packets.push(Packet::from_string(line));
-	}
-
-	packets.push(Packet::from_string("[[2]]"));
-	packets.push(Packet::from_string("[[6]]"));
-
-	packets.sort_by(|a, b| a.compare(b));
-
-	let mut index = 1;
-	let mut product = 1;
-	for packet in &packets {
-		if packet.to_string() == "[[2]]" { product *= index; }
-		else if packet.to_string() == "[[6]]" { product *= index; }
+	return indices.iter().product::<usize>() as u32;
+}
 
-		index += 1;
-	}
+///Explains the comparison of the given pair of packets in the file, counting pairs from 1,
+///in the style of the puzzle walkthrough.
+pub fn explain_pair(filename: &str, pair: usize) -> String {
+	let packets = read_packets(filename);
+	let (left, right) = (&packets[2 * (pair - 1)], &packets[2 * (pair - 1) + 1]);
 
-	product
-}
\ No newline at end of file
+	return format!("== Pair {} ==\n{}", pair, left.explain(right));
+}
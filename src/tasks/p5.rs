@@ -1,31 +1,360 @@
-use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
 use regex::Regex;
 use crate::tasks::helper::get_lines;
 
-fn build_stacks(lines: &Vec<String>) -> (Vec<VecDeque<char>>, usize) {
-	let mut stacks: Vec<VecDeque<char>> = Vec::new();
-	let mut command_start_line = 0;
-	for line in lines {
-		command_start_line += 1;
-		if line.chars().nth(1).unwrap().is_numeric() {
-			for _ in 0..=(line.len()/4) { stacks.push(VecDeque::new()); }
+///Errors that can occur while operating the crane.
+///Stack numbers are reported 1-based, the same way they appear in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CraneError {
+	NoSuchStack(usize),
+	NotEnoughCrates { stack: usize, requested: usize, available: usize },
+	InvalidMove(String),
+	NothingToUndo,
+	NothingToRedo,
+	StepOutOfRange { step: usize, max_step: usize },
+	InvalidDiagram { line: usize, reason: String },
+}
+
+impl fmt::Display for CraneError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			CraneError::NoSuchStack(stack) => write!(f, "stack {} does not exist", stack),
+			CraneError::NotEnoughCrates { stack, requested, available } =>
+				write!(f, "cannot move {} crates from stack {}, it only holds {}", requested, stack, available),
+			CraneError::InvalidMove(line) => write!(f, "invalid move: {}", line),
+			CraneError::NothingToUndo => write!(f, "there is no move to undo"),
+			CraneError::NothingToRedo => write!(f, "there is no move to redo"),
+			CraneError::StepOutOfRange { step, max_step } =>
+				write!(f, "step {} is out of range, the last known step is {}", step, max_step),
+			CraneError::InvalidDiagram { line, reason } => write!(f, "invalid diagram on line {}: {}", line, reason),
+		}
+	}
+}
+
+impl std::error::Error for CraneError {}
+
+///The label written inside the brackets of a crate, such as "Z" for [Z].
+pub type Crate = String;
+
+///A single instruction of the form "move {count} from {from} to {to}".
+///Stack numbers are 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+	pub count: usize,
+	pub from: usize,
+	pub to: usize,
+}
+
+///Decides how a crane puts down the crates it lifted in a single move.
+pub trait CraneModel {
+	///Takes the crates lifted off the top of a stack, bottom-most crate first,
+	///and returns them in the order they end up on the destination stack, bottom-most first.
+	fn place(&self, lifted: Vec<Crate>) -> Vec<Crate>;
+}
+
+///Moves one crate at a time, so the moved crates end up in reverse order.
+pub struct CrateMover9000;
+
+impl CraneModel for CrateMover9000 {
+	fn place(&self, mut lifted: Vec<Crate>) -> Vec<Crate> {
+		lifted.reverse();
+		return lifted;
+	}
+}
+
+///Moves all crates at once, so the moved crates keep their order.
+pub struct CrateMover9001;
+
+impl CraneModel for CrateMover9001 {
+	fn place(&self, lifted: Vec<Crate>) -> Vec<Crate> { lifted }
+}
+
+///Wraps another crane that can only lift a limited number of crates at once.
+///Larger moves are split into several trips, each taking at most `capacity`
+///crates off the top of the source stack.
+pub struct MaxLift<M: CraneModel> {
+	pub model: M,
+	pub capacity: usize,
+}
 
-			break;
+impl<M: CraneModel> CraneModel for MaxLift<M> {
+	fn place(&self, mut lifted: Vec<Crate>) -> Vec<Crate> {
+		let capacity = self.capacity.max(1);
+		let mut placed = Vec::with_capacity(lifted.len());
+		while !lifted.is_empty() {
+			let trip = lifted.split_off(lifted.len().saturating_sub(capacity));
+			placed.extend(self.model.place(trip));
 		}
+
+		return placed;
+	}
+}
+
+//a move that has been applied, along with the crates it lifted so that it can be undone
+struct AppliedMove {
+	movement: Move,
+	lifted: Vec<Crate>,
+}
+
+///A set of crate stacks operated by a crane.
+///Every applied move is recorded, so moves can be undone and redone, and the yard
+///can be rewound or fast-forwarded to any step of its history.
+pub struct CraneYard {
+	stacks: Vec<Vec<Crate>>,
+	model: Box<dyn CraneModel>,
+	history: Vec<AppliedMove>,
+	undone: Vec<Move>,
+}
+
+impl CraneYard {
+	///Creates a yard from stacks given bottom-most crate first.
+	pub fn new(stacks: Vec<Vec<Crate>>, model: Box<dyn CraneModel>) -> CraneYard
+	{ CraneYard { stacks, model, history: Vec::new(), undone: Vec::new() } }
+
+	pub fn stacks(&self) -> &Vec<Vec<Crate>> { &self.stacks }
+
+	///Returns the number of moves currently applied.
+	pub fn step(&self) -> usize { self.history.len() }
+
+	///Returns the moves currently applied, oldest first.
+	pub fn history(&self) -> Vec<Move> {
+		return self.history.iter().map(|applied| applied.movement).collect();
+	}
+
+	///Returns the top crate of each stack.  Empty stacks are skipped.
+	pub fn tops(&self) -> String {
+		return self.stacks.iter().filter_map(|stack| stack.last().map(|c| c.as_str())).collect();
+	}
+
+	///Renders the current stacks in the same drawing format as the puzzle input.
+	pub fn to_diagram(&self) -> String { render_diagram(&self.stacks) }
+
+	fn check_stack(&self, stack: usize) -> Result<(), CraneError> {
+		return if stack == 0 || stack > self.stacks.len() { Err(CraneError::NoSuchStack(stack)) }
+		else { Ok(()) }
 	}
-	
-	for line in &lines[..command_start_line] {
-		for i in 0..=(line.len() / 4)
-		{
-			if line.chars().nth(i* 4 + 1).unwrap() == ' ' {
-				continue;
-			} else if line.chars().nth(i * 4 + 1).unwrap().is_alphabetic(){
-				stacks[i].push_front(line.chars().nth(i * 4 + 1).unwrap());
-			} else { break; }
+
+	//performs a move without touching the redo list
+	fn perform(&mut self, movement: Move) -> Result<(), CraneError> {
+		self.check_stack(movement.from)?;
+		self.check_stack(movement.to)?;
+
+		let source = &mut self.stacks[movement.from - 1];
+		if source.len() < movement.count {
+			return Err(CraneError::NotEnoughCrates {
+				stack: movement.from,
+				requested: movement.count,
+				available: source.len(),
+			});
 		}
+
+		let lifted = source.split_off(source.len() - movement.count);
+		let placed = self.model.place(lifted.clone());
+		self.stacks[movement.to - 1].extend(placed);
+		self.history.push(AppliedMove { movement, lifted });
+
+		return Ok(());
+	}
+
+	///Applies a move.  On error the yard is left untouched.
+	///Applying a new move discards any moves that could have been redone.
+	pub fn apply(&mut self, movement: Move) -> Result<(), CraneError> {
+		self.perform(movement)?;
+		self.undone.clear();
+
+		return Ok(());
+	}
+
+	///Applies every move in order, stopping at the first one that fails.
+	pub fn apply_all(&mut self, moves: &[Move]) -> Result<(), CraneError> {
+		for movement in moves { self.apply(*movement)?; }
+
+		return Ok(());
+	}
+
+	///Reverts the most recently applied move.
+	pub fn undo(&mut self) -> Result<Move, CraneError> {
+		let applied = self.history.pop().ok_or(CraneError::NothingToUndo)?;
+		let destination = &mut self.stacks[applied.movement.to - 1];
+		destination.truncate(destination.len() - applied.lifted.len());
+		self.stacks[applied.movement.from - 1].extend(applied.lifted);
+		self.undone.push(applied.movement);
+
+		return Ok(applied.movement);
+	}
+
+	///Re-applies the most recently undone move.
+	pub fn redo(&mut self) -> Result<Move, CraneError> {
+		let movement = self.undone.pop().ok_or(CraneError::NothingToRedo)?;
+		self.perform(movement)?;
+
+		return Ok(movement);
 	}
 
-	return (stacks, command_start_line + 1)
+	///Undoes or redoes moves until exactly `step` moves are applied.
+	pub fn replay_to(&mut self, step: usize) -> Result<(), CraneError> {
+		let max_step = self.history.len() + self.undone.len();
+		if step > max_step { return Err(CraneError::StepOutOfRange { step, max_step }); }
+
+		while self.history.len() > step { self.undo()?; }
+		while self.history.len() < step { self.redo()?; }
+
+		return Ok(());
+	}
+}
+
+//finds the character span of every whitespace separated token in a line,
+//returned as (first column, last column, token)
+fn tokenize_columns(line: &str) -> Vec<(usize, usize, String)> {
+	let mut tokens = Vec::new();
+	let mut current: Option<(usize, String)> = None;
+	for (column, c) in line.chars().enumerate() {
+		if c.is_whitespace() {
+			if let Some((start, token)) = current.take() { tokens.push((start, column - 1, token)); }
+		} else {
+			current.get_or_insert((column, String::new())).1.push(c);
+		}
+	}
+
+	if let Some((start, token)) = current {
+		let end = start + token.chars().count() - 1;
+		tokens.push((start, end, token));
+	}
+
+	return tokens;
+}
+
+//finds the character span of every [crate] in a line, returned as (first column, last column, crate)
+fn bracketed_crates(line: &str, line_number: usize) -> Result<Vec<(usize, usize, Crate)>, CraneError> {
+	let error = |reason: String| CraneError::InvalidDiagram { line: line_number, reason };
+	let mut crates = Vec::new();
+	let mut current: Option<(usize, Crate)> = None;
+	for (column, c) in line.chars().enumerate() {
+		match (c, &mut current) {
+			('[', None) => current = Some((column, Crate::new())),
+			(']', Some(_)) => {
+				let (start, name) = current.take().unwrap();
+				if name.is_empty() { return Err(error(format!("empty crate at column {}", start + 1))); }
+
+				crates.push((start, column, name));
+			}
+			('[', Some(_)) | (']', None) => return Err(error(format!("unbalanced bracket at column {}", column + 1))),
+			(c, Some((_, name))) => {
+				if c.is_whitespace() { return Err(error(format!("whitespace inside crate at column {}", column + 1))); }
+
+				name.push(c);
+			}
+			(c, None) => {
+				if !c.is_whitespace() { return Err(error(format!("unexpected '{}' at column {}", c, column + 1))); }
+			}
+		}
+	}
+
+	return match current {
+		Some((start, _)) => Err(error(format!("unclosed crate at column {}", start + 1))),
+		None => Ok(crates),
+	}
+}
+
+///Parses the drawing of the starting stacks, ending with the line of stack numbers.
+///Crates are assigned to the stack whose number they sit above, so lines may be ragged,
+///crates may have labels longer than one character, and there may be any number of stacks.
+///Returns the stacks with their bottom-most crate first.
+pub fn parse_diagram(lines: &[String]) -> Result<Vec<Vec<Crate>>, CraneError> {
+	let label_index = lines.iter().rposition(|line| !line.trim().is_empty())
+		.ok_or(CraneError::InvalidDiagram { line: 1, reason: "missing stack numbers".to_string() })?;
+
+	let labels = tokenize_columns(&lines[label_index]);
+	for (i, (start, _, label)) in labels.iter().enumerate() {
+		if label.parse::<usize>().ok() != Some(i + 1) {
+			return Err(CraneError::InvalidDiagram {
+				line: label_index + 1,
+				reason: format!("expected stack number {} at column {}, found '{}'", i + 1, start + 1, label),
+			});
+		}
+	}
+
+	let mut stacks: Vec<Vec<Crate>> = vec![Vec::new(); labels.len()];
+	for (line_index, line) in lines[..label_index].iter().enumerate().rev() {
+		let mut filled = vec![false; labels.len()];
+		for (start, end, name) in bracketed_crates(line, line_index + 1)? {
+			let error = |reason: &str| CraneError::InvalidDiagram {
+				line: line_index + 1,
+				reason: format!("crate [{}] at column {} {}", name, start + 1, reason),
+			};
+
+			let stack = labels.iter().position(|&(label_start, label_end, _)| label_start <= end && start <= label_end)
+				.ok_or_else(|| error("is not above a stack number"))?;
+			if filled[stack] { return Err(error("shares a stack with another crate on the same line")); }
+			if stacks[stack].len() != label_index - line_index - 1 { return Err(error("is floating above an empty spot")); }
+
+			filled[stack] = true;
+			stacks[stack].push(name);
+		}
+	}
+
+	return Ok(stacks);
+}
+
+///Renders stacks, given bottom-most crate first, in the drawing format of the puzzle input.
+///Each column is wide enough for its widest crate and its stack number, and trailing
+///whitespace is trimmed so the output round-trips through `parse_diagram`.
+pub fn render_diagram(stacks: &[Vec<Crate>]) -> String {
+	let widths: Vec<usize> = stacks.iter().enumerate().map(|(i, stack)| {
+		let widest_crate = stack.iter().map(|name| name.chars().count() + 2).max().unwrap_or(3);
+		widest_crate.max((i + 1).to_string().len() + 2)
+	}).collect();
+	let height = stacks.iter().map(|stack| stack.len()).max().unwrap_or(0);
+
+	let mut lines: Vec<String> = Vec::with_capacity(height + 1);
+	for level in (0..height).rev() {
+		let cells: Vec<String> = stacks.iter().zip(&widths).map(|(stack, &width)| match stack.get(level) {
+			Some(name) => format!("{:<width$}", format!("[{}]", name), width = width),
+			None => " ".repeat(width),
+		}).collect();
+		lines.push(cells.join(" ").trim_end().to_string());
+	}
+
+	let labels: Vec<String> = widths.iter().enumerate()
+		.map(|(i, &width)| format!("{:^width$}", i + 1, width = width))
+		.collect();
+	lines.push(labels.join(" ").trim_end().to_string());
+
+	return lines.join("\n");
+}
+
+///Writes the current stacks of a yard to a file in the drawing format of the puzzle input.
+///The file can be read back with `load_yard`.
+pub fn save_yard(yard: &CraneYard, filename: &str) -> std::io::Result<()> {
+	return fs::write(filename, yard.to_diagram() + "\n");
+}
+
+///Parses lines of the form "move {count} from {from} to {to}".  Blank lines are ignored.
+pub fn parse_moves(lines: &[String]) -> Result<Vec<Move>, CraneError> {
+	let pattern = Regex::new(r"^move (\d+) from (\d+) to (\d+)$").unwrap();
+	let mut moves = Vec::new();
+	for line in lines {
+		if line.trim().is_empty() { continue; }
+
+		let command = pattern.captures(line.trim()).ok_or_else(|| CraneError::InvalidMove(line.clone()))?;
+		let parse = |i: usize| command[i].parse::<usize>().map_err(|_| CraneError::InvalidMove(line.clone()));
+		moves.push(Move { count: parse(1)?, from: parse(2)?, to: parse(3)? });
+	}
+
+	return Ok(moves);
+}
+
+///Reads a file containing the drawing of the starting stacks, followed by a blank line and
+///the list of moves.  The moves may be left out, such as for files written by `save_yard`.
+///Returns a yard operated by the given crane, along with the moves that have yet to be applied.
+pub fn load_yard(filename: &str, model: Box<dyn CraneModel>) -> Result<(CraneYard, Vec<Move>), CraneError> {
+	let lines = get_lines(filename);
+	let diagram_end = lines.iter().position(|line| line.trim().is_empty()).unwrap_or(lines.len());
+	let stacks = parse_diagram(&lines[..diagram_end])?;
+	let moves = parse_moves(&lines[diagram_end..])?;
+
+	return Ok((CraneYard::new(stacks, model), moves));
 }
 
 ///Takes in a file that contains several stacks of crates and
@@ -33,24 +362,10 @@ fn build_stacks(lines: &Vec<String>) -> (Vec<VecDeque<char>>, usize) {
 ///This function will return a string of the top crate in each stack
 ///after all movements have been made.
 pub fn top_crate_stacks(filename: &str) -> String {
-	let lines = get_lines(filename);
-	let (mut stacks, command) = build_stacks(&lines);
-	let pattern = Regex::new(r"move (\d+) from (\d+) to (\d+)").unwrap();
-	for line in &lines[command..] {
-		let command = pattern.captures(line).unwrap();
-		let box_count: usize = command[1].parse().unwrap();
-		let from: usize = command[2].parse().unwrap();
-		let to: usize = command[3].parse().unwrap();
-		for _ in 0..box_count {
-			let crate_top = stacks[from - 1].pop_back().unwrap();
-			stacks[to - 1].push_back(crate_top);
-		}
-	}
-
-	let mut result: String = String::new();
-	for stack in stacks { result.push(stack[stack.len() - 1]); }
+	let (mut yard, moves) = load_yard(filename, Box::new(CrateMover9000)).expect("Invalid input");
+	yard.apply_all(&moves).expect("Invalid move");
 
-	return result;
+	return yard.tops();
 }
 
 ///Takes in a file that contains several stacks of crates and
@@ -59,28 +374,8 @@ pub fn top_crate_stacks(filename: &str) -> String {
 ///This function will return a string of the top crate in each stack
 ///after all movements have been made.
 pub fn top_crate_stacks_ordered(filename: &str) -> String {
-	let lines = get_lines(filename);
-	let (mut stacks, command) = build_stacks(&lines);
-	let pattern = Regex::new(r"move (\d+) from (\d+) to (\d+)").unwrap();
-	let mut temp_stack: Vec<char> = Vec::new();
-	for line in &lines[command..] {
-		let command = pattern.captures(line).unwrap();
-		let box_count: usize = command[1].parse().unwrap();
-		let from: usize = command[2].parse().unwrap();
-		let to: usize = command[3].parse().unwrap();
-		for _ in 0..box_count {
-			let crate_top = stacks[from - 1].pop_back().unwrap();
-			temp_stack.push(crate_top);
-		}
-
-		for _ in 0..temp_stack.len() {
-			let crate_top = temp_stack.pop().unwrap();
-			stacks[to - 1].push_back(crate_top);
-		}
-	}
-
-	let mut result: String = String::new();
-	for stack in stacks { result.push(stack[stack.len() - 1]); }
+	let (mut yard, moves) = load_yard(filename, Box::new(CrateMover9001)).expect("Invalid input");
+	yard.apply_all(&moves).expect("Invalid move");
 
-	return result;
-}
\ No newline at end of file
+	return yard.tops();
+}
@@ -1,25 +1,179 @@
-use std::fs;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, Bytes, Read};
 
-///The input is a file containing a single data stream.
-///In order to find the packet within the data stream, we need to find the start
-///of the packet, which is denoted by n different characters in a row.
-///This function returns the index of the first character of the message.
-pub fn find_packet_start(filename: &str, n: usize) -> usize {
-	let data = fs::read_to_string(filename).expect("Unable to read file");
-	let mut message_start = n;
-	for i in 0..data.len() - n {
-		let sub_message = &data[i..i + n];
-		let mut found = true;
-		for i in 1..n {
-			if sub_message[i..].contains(sub_message.chars().nth(i - 1).unwrap()) {
-				message_start += 1;
-				found = false;
-				break;
+///The number of distinct characters that mark the start of a packet.
+pub const PACKET_MARKER_LEN: usize = 4;
+
+///The number of distinct characters that mark the start of a message.
+pub const MESSAGE_MARKER_LEN: usize = 14;
+
+///Keeps track of the last `window` bytes of a data stream along with how many
+///times each byte value appears in them, so checking whether the window holds
+///only distinct bytes takes constant time per byte.
+pub struct MarkerDetector {
+	window: usize,
+	recent: VecDeque<u8>,
+	counts: [usize; 256],
+	duplicates: usize,  // the number of byte values that appear more than once in the window
+	position: usize,    // the number of bytes pushed so far
+}
+
+impl MarkerDetector {
+	pub fn new(window: usize) -> MarkerDetector {
+		assert!(window > 0, "marker length must be at least 1");
+		MarkerDetector { window, recent: VecDeque::with_capacity(window), counts: [0; 256], duplicates: 0, position: 0 }
+	}
+
+	///Adds the next byte of the stream.  If the last `window` bytes are all different,
+	///returns the number of bytes processed so far, which is the position right after the marker.
+	pub fn push(&mut self, byte: u8) -> Option<usize> {
+		if self.recent.len() == self.window {
+			let oldest = self.recent.pop_front().unwrap() as usize;
+			self.counts[oldest] -= 1;
+			if self.counts[oldest] == 1 { self.duplicates -= 1; }
+		}
+
+		self.recent.push_back(byte);
+		self.counts[byte as usize] += 1;
+		if self.counts[byte as usize] == 2 { self.duplicates += 1; }
+		self.position += 1;
+
+		return if self.recent.len() == self.window && self.duplicates == 0 { Some(self.position) }
+		else { None }
+	}
+
+	///Returns the number of bytes processed so far.
+	pub fn position(&self) -> usize { self.position }
+
+	///Forgets the current window, so the next marker must be made up entirely of bytes
+	///pushed after this call.  The position keeps counting from where it was.
+	pub fn reset(&mut self) {
+		self.recent.clear();
+		self.counts = [0; 256];
+		self.duplicates = 0;
+	}
+}
+
+///Iterator over every marker position in a data stream.
+///Line breaks are not part of the data stream and are skipped.
+pub struct Markers<R: Read> {
+	bytes: Bytes<BufReader<R>>,
+	detector: MarkerDetector,
+}
+
+impl<R: Read> Iterator for Markers<R> {
+	type Item = io::Result<usize>;
+
+	fn next(&mut self) -> Option<io::Result<usize>> {
+		for byte in self.bytes.by_ref() {
+			match byte {
+				Ok(b'\n') | Ok(b'\r') => continue,
+				Ok(byte) => if let Some(position) = self.detector.push(byte) { return Some(Ok(position)); },
+				Err(error) => return Some(Err(error)),
+			}
+		}
+
+		return None;
+	}
+}
+
+///Reads a data stream and yields the position right after every window of `n` different
+///characters, in order.  Runs in O(1) per character without holding the stream in memory.
+pub fn markers<R: Read>(reader: R, n: usize) -> Markers<R> {
+	return Markers { bytes: BufReader::new(reader).bytes(), detector: MarkerDetector::new(n) };
+}
+
+///Returns the position right after the first window of `n` different characters,
+///or None if the stream ends before such a window is found.
+pub fn find_marker<R: Read>(reader: R, n: usize) -> io::Result<Option<usize>> {
+	return markers(reader, n).next().transpose();
+}
+
+///A message in the data stream, running from the end of one start-of-message marker
+///up to the start of the next one, or to the end of the stream for the last message.
+///Offsets are positions in the data stream, not counting line breaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+	pub marker_offset: usize,  // the position of the first character of the start-of-message marker
+	pub offset: usize,         // the position of the first character of the message
+	pub data: Vec<u8>,
+}
+
+///Iterator that splits a data stream into frames.
+///Start-of-message markers are searched for from the start of the stream, but a frame only begins at
+///one that ends once the start-of-packet marker has been found, so the first message marker may
+///overlap the packet marker.  Message markers never overlap each other, so each one is made up of
+///characters that come after the previous one.
+pub struct Frames<R: Read> {
+	bytes: Bytes<BufReader<R>>,
+	packet_detector: MarkerDetector,
+	detector: MarkerDetector,
+	message_marker_len: usize,
+	in_packet: bool,               // whether the start-of-packet marker has been found
+	current: Option<(usize, usize)>, // the marker offset and offset of the frame being read
+	pending: Vec<u8>,              // the characters read since the last marker, or the last few before the first one
+}
+
+impl<R: Read> Frames<R> {
+	//starts a new frame whose marker ends at the given position, returning the frame that it ends
+	fn start_frame(&mut self, position: usize) -> Option<Frame> {
+		let marker_start = self.pending.len() - self.message_marker_len;
+		let data: Vec<u8> = self.pending.drain(..).take(marker_start).collect();
+		let finished = self.current.map(|(marker_offset, offset)| Frame { marker_offset, offset, data });
+		self.current = Some((position - self.message_marker_len, position));
+		self.detector.reset();
+
+		return finished;
+	}
+}
+
+impl<R: Read> Iterator for Frames<R> {
+	type Item = io::Result<Frame>;
+
+	fn next(&mut self) -> Option<io::Result<Frame>> {
+		while let Some(byte) = self.bytes.next() {
+			let byte = match byte {
+				Ok(b'\n') | Ok(b'\r') => continue,
+				Ok(byte) => byte,
+				Err(error) => return Some(Err(error)),
+			};
+
+			if !self.in_packet && self.packet_detector.push(byte).is_some() { self.in_packet = true; }
+
+			let marker = self.detector.push(byte);
+			self.pending.push(byte);
+			if self.current.is_none() && self.pending.len() > self.message_marker_len { self.pending.remove(0); }
+
+			if let Some(position) = marker.filter(|_| self.in_packet) {
+				if let Some(frame) = self.start_frame(position) { return Some(Ok(frame)); }
 			}
 		}
 
-		if found { break; }
+		let (marker_offset, offset) = self.current.take()?;
+		return Some(Ok(Frame { marker_offset, offset, data: std::mem::take(&mut self.pending) }));
 	}
+}
+
+///Splits a data stream into the messages that follow each start-of-message marker.
+///The marker lengths are configurable, the puzzle uses `PACKET_MARKER_LEN` and `MESSAGE_MARKER_LEN`.
+pub fn frames<R: Read>(reader: R, packet_marker_len: usize, message_marker_len: usize) -> Frames<R> {
+	return Frames {
+		bytes: BufReader::new(reader).bytes(),
+		packet_detector: MarkerDetector::new(packet_marker_len),
+		detector: MarkerDetector::new(message_marker_len),
+		message_marker_len,
+		in_packet: false,
+		current: None,
+		pending: Vec::new(),
+	};
+}
 
-	return message_start;
-}
\ No newline at end of file
+///The input is a file containing a single data stream.
+///In order to find the packet within the data stream, we need to find the start
+///of the packet, which is denoted by n different characters in a row.
+///This function returns the index of the first character of the message.
+pub fn find_packet_start(filename: &str, n: usize) -> usize {
+	let file = File::open(filename).expect("Unable to read file");
+	return find_marker(file, n).expect("Unable to read file").expect("No marker found");
+}
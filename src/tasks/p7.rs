@@ -1,101 +1,618 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::fmt;
+use serde::{Deserialize, Serialize};
 use crate::tasks::helper::get_lines;
 
-#[derive(Default)]
-pub struct Folder {
+///Index of a file or directory within a `FileSystem`.
+pub type NodeId = usize;
+
+///The id of the root directory "/".
+pub const ROOT: NodeId = 0;
+
+///Errors from changing the filesystem or replaying a terminal transcript.
+///Paths are reported as full paths, such as "/a/e".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsError {
+	NotFound(String),
+	NotADirectory(String),
+	DuplicateName(String),
+	InvalidName(String),
+	AboveRoot,
+	CannotModifyRoot,
+	MoveIntoItself(String),
+	RemoveWorkingDirectory(String),
+	ListingMismatch(String),
+	UnexpectedOutput(String),
+	UnknownCommand(String),
+	InvalidLine(String),
+	InvalidJson(String),
+}
+
+impl fmt::Display for FsError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			FsError::NotFound(path) => write!(f, "{} does not exist", path),
+			FsError::NotADirectory(path) => write!(f, "{} is not a directory", path),
+			FsError::DuplicateName(path) => write!(f, "{} already exists", path),
+			FsError::InvalidName(name) => write!(f, "invalid name \"{}\"", name),
+			FsError::AboveRoot => write!(f, "cannot go above the root directory"),
+			FsError::CannotModifyRoot => write!(f, "the root directory cannot be moved or removed"),
+			FsError::MoveIntoItself(path) => write!(f, "cannot move {} into itself", path),
+			FsError::RemoveWorkingDirectory(path) => write!(f, "cannot remove {}, it holds the working directory", path),
+			FsError::ListingMismatch(path) => write!(f, "listing of {} does not match its known contents", path),
+			FsError::UnexpectedOutput(line) => write!(f, "output outside of a listing: {}", line),
+			FsError::UnknownCommand(line) => write!(f, "unknown command: {}", line),
+			FsError::InvalidLine(line) => write!(f, "invalid line: {}", line),
+			FsError::InvalidJson(error) => write!(f, "invalid json: {}", error),
+		}
+	}
+}
+
+impl std::error::Error for FsError {}
+
+///An error found while replaying a terminal transcript, along with its 1-based line number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayError {
+	pub line: usize,
+	pub error: FsError,
+}
+
+impl fmt::Display for ReplayError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}: {}", self.line, self.error)
+	}
+}
+
+impl std::error::Error for ReplayError {}
+
+enum NodeKind {
+	Directory(Vec<NodeId>),  // the children of the directory, in the order they were added
+	File,
+}
+
+struct Node {
 	name: String,
-	children_folders: Vec<Rc<RefCell<Folder>>>,
-	children_files: Vec<Rc<RefCell<File>>>,
-	parent: Option<Rc<RefCell<Folder>>>,
+	parent: Option<NodeId>,
+	size: u64,      // the size of the file, or the total size of everything within the directory
+	listed: bool,   // whether the contents of the directory have been seen through `ls`
+	kind: NodeKind,
+}
+
+///A line of the `du` style report.  `depth` is the number of levels below the directory the report started from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageEntry {
+	pub id: NodeId,
+	pub path: String,
+	pub size: u64,
+	pub depth: usize,
+}
+
+//the directories of a filesystem laid out for the search in `smallest_directories_to_delete`
+struct DeletionPlan {
+	order: Vec<NodeId>,     // the directories, parents before their children
+	sizes: Vec<u64>,
+	subtree_end: Vec<usize>,  // the index in order after the last directory within each directory
+	available: Vec<u64>,      // the most that can still be freed by deleting directories from each index onward
+}
+
+impl DeletionPlan {
+	fn new(file_system: &FileSystem) -> DeletionPlan {
+		let mut plan = DeletionPlan { order: Vec::new(), sizes: Vec::new(), subtree_end: Vec::new(), available: Vec::new() };
+		plan.lay_out(file_system, ROOT);
+
+		// once every directory before i has been decided, the directories still available are
+		// the ones whose parent comes before i, and deleting them all frees the most space.
+		// Those are i itself and then the ones still available once its subtree has been skipped.
+		plan.available = vec![0; plan.order.len() + 1];
+		for i in (0..plan.order.len()).rev() {
+			plan.available[i] = plan.sizes[i] + plan.available[plan.subtree_end[i]];
+		}
+
+		return plan;
+	}
+
+	//adds a directory and everything within it, larger subdirectories first so that
+	//the search finds close fits early
+	fn lay_out(&mut self, file_system: &FileSystem, id: NodeId) {
+		let index = self.order.len();
+		self.order.push(id);
+		self.sizes.push(file_system.size(id));
+		self.subtree_end.push(0);
+
+		let mut children: Vec<NodeId> = file_system.children(id).iter().copied().filter(|&child| file_system.is_directory(child)).collect();
+		children.sort_by_key(|&child| std::cmp::Reverse(file_system.size(child)));
+		for child in children { self.lay_out(file_system, child); }
+
+		self.subtree_end[index] = self.order.len();
+	}
+
+	//decides for each directory from index i onward whether to delete it, keeping track
+	//of the smallest combination found so far that frees at least `needed`.
+	//Returns true once a combination that frees exactly `needed` is found, since nothing can beat it.
+	fn search(&self, i: usize, freed: u64, needed: u64, chosen: &mut Vec<NodeId>, best: &mut u64, best_set: &mut Vec<NodeId>) -> bool {
+		if freed >= needed {
+			if freed < *best {
+				*best = freed;
+				*best_set = chosen.clone();
+			}
+
+			return freed == needed;
+		}
+
+		if i == self.order.len() || freed + self.available[i] < needed || freed >= *best { return false; }
+
+		chosen.push(self.order[i]);
+		let exact = self.search(self.subtree_end[i], freed + self.sizes[i], needed, chosen, best, best_set);
+		chosen.pop();
+
+		return exact || self.search(i + 1, freed, needed, chosen, best, best_set);
+	}
+}
+
+//the JSON form of a node.  The size of a directory is written out for other tools to read,
+//but it is optional when loading since it is recomputed from the files.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonNode {
+	Directory {
+		name: String,
+		#[serde(default, skip_deserializing)]
+		size: u64,
+		#[serde(default)]
+		children: Vec<JsonNode>,
+	},
+	File {
+		name: String,
+		size: u64,
+	},
+}
+
+//an entry printed by `ls`, with the size for files and None for directories
+type ListingEntry = (String, Option<u64>);
+
+///A filesystem tree stored in a single arena.  Nodes refer to each other by id, and
+///the total size of every directory is kept up to date as files are added, moved or removed.
+///Ids of removed nodes are never reused, but they should not be used after removal.
+pub struct FileSystem {
+	nodes: Vec<Node>,
+}
+
+impl Default for FileSystem {
+	fn default() -> FileSystem { FileSystem::new() }
 }
 
-impl Folder {
-	pub fn get_size(&self) -> u64 {
-		let mut sum = 0;
-		for child in &self.children_files { sum += child.borrow().size; }
-		for child in &self.children_folders { sum += child.borrow().get_size(); }
+impl FileSystem {
+	///Creates a filesystem that only contains the root directory.
+	pub fn new() -> FileSystem {
+		let root = Node { name: "/".to_string(), parent: None, size: 0, listed: false, kind: NodeKind::Directory(Vec::new()) };
+		FileSystem { nodes: vec![root] }
+	}
+
+	//adds size to a directory and all of its ancestors, a negative size subtracts it
+	fn propagate_size(&mut self, from: Option<NodeId>, size: i64) {
+		let mut ancestor = from;
+		while let Some(current) = ancestor {
+			self.nodes[current].size = self.nodes[current].size.wrapping_add_signed(size);
+			ancestor = self.nodes[current].parent;
+		}
+	}
+
+	//links an existing node into a directory under the given name.
+	//Names that paths could not refer to, such as "..", are rejected.
+	fn attach(&mut self, id: NodeId, parent: NodeId, name: &str) -> Result<(), FsError> {
+		if name.is_empty() || name == "." || name == ".." || name.contains('/') { return Err(FsError::InvalidName(name.to_string())); }
+		if !self.is_directory(parent) { return Err(FsError::NotADirectory(self.path(parent))); }
+		if self.child(parent, name).is_some() { return Err(FsError::DuplicateName(self.child_path(parent, name))); }
+
+		if let NodeKind::Directory(children) = &mut self.nodes[parent].kind { children.push(id); }
+		self.nodes[id].name = name.to_string();
+		self.nodes[id].parent = Some(parent);
+		self.propagate_size(Some(parent), self.nodes[id].size as i64);
+
+		return Ok(());
+	}
+
+	//unlinks a node from its parent directory
+	fn detach(&mut self, id: NodeId) -> Result<(), FsError> {
+		let parent = self.nodes[id].parent.ok_or(FsError::CannotModifyRoot)?;
+		if let NodeKind::Directory(children) = &mut self.nodes[parent].kind { children.retain(|&child| child != id); }
+		self.nodes[id].parent = None;
+		self.propagate_size(Some(parent), -(self.nodes[id].size as i64));
+
+		return Ok(());
+	}
+
+	fn add_node(&mut self, parent: NodeId, name: &str, size: u64, kind: NodeKind) -> Result<NodeId, FsError> {
+		let id = self.nodes.len();
+		self.nodes.push(Node { name: String::new(), parent: None, size, listed: false, kind });
+		if let Err(error) = self.attach(id, parent, name) {
+			self.nodes.pop();
+			return Err(error);
+		}
+
+		return Ok(id);
+	}
+
+	///Adds an empty directory to the given directory and returns its id.
+	pub fn add_directory(&mut self, parent: NodeId, name: &str) -> Result<NodeId, FsError> {
+		return self.add_node(parent, name, 0, NodeKind::Directory(Vec::new()));
+	}
+
+	///Adds a file to the given directory and returns its id.
+	pub fn add_file(&mut self, parent: NodeId, name: &str, size: u64) -> Result<NodeId, FsError> {
+		return self.add_node(parent, name, size, NodeKind::File);
+	}
+
+	///Removes a file, or a directory along with everything within it.
+	pub fn remove(&mut self, id: NodeId) -> Result<(), FsError> {
+		return self.detach(id);
+	}
+
+	///Moves a node into the given directory under a new name.
+	pub fn move_node(&mut self, id: NodeId, new_parent: NodeId, new_name: &str) -> Result<(), FsError> {
+		if id == ROOT { return Err(FsError::CannotModifyRoot); }
+
+		let mut ancestor = Some(new_parent);
+		while let Some(current) = ancestor {
+			if current == id { return Err(FsError::MoveIntoItself(self.path(id))); }
+			ancestor = self.nodes[current].parent;
+		}
+
+		let (old_parent, old_name) = (self.nodes[id].parent.unwrap(), self.nodes[id].name.clone());
+		self.detach(id)?;
+		if let Err(error) = self.attach(id, new_parent, new_name) {
+			self.attach(id, old_parent, &old_name).unwrap();
+			return Err(error);
+		}
+
+		return Ok(());
+	}
+
+	pub fn name(&self, id: NodeId) -> &str { &self.nodes[id].name }
+
+	pub fn parent(&self, id: NodeId) -> Option<NodeId> { self.nodes[id].parent }
+
+	///Returns the size of a file, or the total size of all files within a directory.
+	pub fn size(&self, id: NodeId) -> u64 { self.nodes[id].size }
+
+	pub fn is_directory(&self, id: NodeId) -> bool { matches!(self.nodes[id].kind, NodeKind::Directory(_)) }
+
+	///Returns the children of a directory, or nothing for a file.
+	pub fn children(&self, id: NodeId) -> &[NodeId] {
+		return match &self.nodes[id].kind {
+			NodeKind::Directory(children) => children,
+			NodeKind::File => &[],
+		}
+	}
+
+	///Returns the child of a directory with the given name.
+	pub fn child(&self, id: NodeId, name: &str) -> Option<NodeId> {
+		return self.children(id).iter().copied().find(|&child| self.nodes[child].name == name);
+	}
+
+	///Returns the full path of a node, such as "/a/e".
+	pub fn path(&self, id: NodeId) -> String {
+		let mut names = Vec::new();
+		let mut current = id;
+		while let Some(parent) = self.nodes[current].parent {
+			names.push(self.nodes[current].name.as_str());
+			current = parent;
+		}
+
+		names.reverse();
+		return format!("/{}", names.join("/"));
+	}
+
+	//the full path that a child with the given name would have
+	fn child_path(&self, parent: NodeId, name: &str) -> String {
+		return if parent == ROOT { format!("/{}", name) }
+		else { format!("{}/{}", self.path(parent), name) }
+	}
+
+	///Finds the node at an absolute path, such as "/a/e".
+	pub fn lookup(&self, path: &str) -> Option<NodeId> {
+		return self.resolve(ROOT, path).ok();
+	}
+
+	///Finds the node at a path relative to the given directory.  Paths starting
+	///with "/" are absolute, and ".." and "." refer to the parent and the directory itself.
+	pub fn resolve(&self, from: NodeId, path: &str) -> Result<NodeId, FsError> {
+		let mut current = if path.starts_with('/') { ROOT } else { from };
+		for name in path.split('/').filter(|name| !name.is_empty()) {
+			current = match name {
+				"." => current,
+				".." => self.parent(current).ok_or(FsError::AboveRoot)?,
+				name => {
+					if !self.is_directory(current) { return Err(FsError::NotADirectory(self.path(current))); }
+					self.child(current, name).ok_or_else(|| FsError::NotFound(self.child_path(current, name)))?
+				}
+			};
+		}
+
+		return Ok(current);
+	}
+
+	//splits a path into the directory it points into and its last name, such as
+	//"a/e" into the id of "a" and "e"
+	fn resolve_parent<'a>(&self, from: NodeId, path: &'a str) -> Result<(NodeId, &'a str), FsError> {
+		let trimmed = path.trim_end_matches('/');
+		let (parent, name) = match trimmed.rfind('/') {
+			Some(split) => (self.resolve(from, &path[..=split])?, &trimmed[split + 1..]),
+			None => (from, trimmed),
+		};
+
+		return match name {
+			"" | "." | ".." => Err(FsError::InvalidLine(path.to_string())),
+			name => Ok((parent, name)),
+		}
+	}
+
+	///Iterates over a node and everything within it, parents before their children.
+	pub fn descendants(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+		let mut stack = vec![id];
+		return std::iter::from_fn(move || {
+			let current = stack.pop()?;
+			stack.extend(self.children(current).iter().rev());
+			Some(current)
+		});
+	}
+
+	///Iterates over every directory, including the root.
+	pub fn directories(&self) -> impl Iterator<Item = NodeId> + '_ {
+		return self.descendants(ROOT).filter(|&id| self.is_directory(id));
+	}
+
+	///Iterates over every file.
+	pub fn files(&self) -> impl Iterator<Item = NodeId> + '_ {
+		return self.descendants(ROOT).filter(|&id| !self.is_directory(id));
+	}
+
+	///Returns the sum of the sizes of all directories that are at most `limit` in size.
+	pub fn sum_small_directories(&self, limit: u64) -> u64 {
+		return self.directories().map(|id| self.size(id)).filter(|&size| size <= limit).sum();
+	}
 
-		return sum;
+	///Returns the size of the directories within `from`, including `from` itself, in the order
+	///`du` prints them: every directory comes after everything within it.  Directories nested more
+	///than `max_depth` levels below `from` are left out, but their sizes still count towards their parents.
+	pub fn disk_usage(&self, from: NodeId, max_depth: Option<usize>) -> Vec<UsageEntry> {
+		let mut entries = Vec::new();
+		self.collect_usage(from, 0, max_depth, &mut entries);
+
+		return entries;
 	}
 
-	pub fn get_sum_small_directories(&self) -> u64 {
-		let mut sum = 0;
-		if self.get_size() < 100000 { sum += self.get_size(); }
+	fn collect_usage(&self, id: NodeId, depth: usize, max_depth: Option<usize>, entries: &mut Vec<UsageEntry>) {
+		if max_depth.is_some_and(|max_depth| depth > max_depth) { return; }
+
+		for &child in self.children(id) {
+			if self.is_directory(child) { self.collect_usage(child, depth + 1, max_depth, entries); }
+		}
 
-		for child in &self.children_folders { sum += child.borrow().get_sum_small_directories(); }
+		entries.push(UsageEntry { id, path: self.path(id), size: self.size(id), depth });
+	}
+
+	///Formats `disk_usage` like the output of `du`, one "{size}\t{path}" line per directory.
+	pub fn du_report(&self, from: NodeId, max_depth: Option<usize>) -> String {
+		return self.disk_usage(from, max_depth).iter()
+			.map(|entry| format!("{}\t{}", entry.size, entry.path))
+			.collect::<Vec<String>>()
+			.join("\n");
+	}
+
+	///Returns how much space has to be freed to leave `required` free on a disk of size `total`.
+	pub fn space_needed(&self, total: u64, required: u64) -> u64 {
+		return (self.size(ROOT) + required).saturating_sub(total);
+	}
 
-		return sum;
+	///Returns the smallest directory that frees up enough space to leave `required` free
+	///on a disk of size `total` once deleted.
+	pub fn smallest_directory_to_delete(&self, total: u64, required: u64) -> Option<NodeId> {
+		let needed = self.space_needed(total, required);
+		return self.directories().filter(|&id| self.size(id) >= needed).min_by_key(|&id| self.size(id));
 	}
 
-	pub fn in_order_folder_traversal(&self) -> Vec<(String, u64)> {
-		let mut result = Vec::new();
-		for child in &self.children_folders {
-			let mut child_result = child.borrow().in_order_folder_traversal();
-			result.append(&mut child_result);
+	///Returns the set of directories with the smallest combined size that frees up enough space
+	///to leave `required` free on a disk of size `total` once deleted.  No directory in the set
+	///is within another one.  This is an exact branch and bound search that stops early on a
+	///perfect fit, but it can still take exponential time on unlucky trees.
+	pub fn smallest_directories_to_delete(&self, total: u64, required: u64) -> Option<Vec<NodeId>> {
+		let needed = self.space_needed(total, required);
+		let single = self.smallest_directory_to_delete(total, required)?;
+		let mut best = self.size(single);
+		let mut best_set = vec![single];
+		DeletionPlan::new(self).search(0, 0, needed, &mut Vec::new(), &mut best, &mut best_set);
+
+		return Some(best_set);
+	}
+
+	//checks the output of `ls` against what is already known about the directory.
+	//Entries that are new are added, unless the directory has already been listed,
+	//in which case the listing must match its contents exactly.
+	fn apply_listing(&mut self, directory: NodeId, entries: &[ListingEntry]) -> Result<(), FsError> {
+		let mismatch = || FsError::ListingMismatch(self.path(directory));
+		for (i, (name, _)) in entries.iter().enumerate() {
+			if entries[..i].iter().any(|(other, _)| other == name) {
+				return Err(FsError::DuplicateName(self.child_path(directory, name)));
+			}
+		}
+
+		for &child in self.children(directory) {
+			if !entries.iter().any(|(name, _)| *name == self.nodes[child].name) { return Err(mismatch()); }
+		}
+
+		let mut added = Vec::new();
+		for (name, size) in entries {
+			match (self.child(directory, name), size) {
+				(Some(child), None) => if !self.is_directory(child) { return Err(mismatch()); },
+				(Some(child), Some(size)) => if self.is_directory(child) || self.size(child) != *size { return Err(mismatch()); },
+				(None, _) => if self.nodes[directory].listed { return Err(mismatch()); } else { added.push((name, size)); },
+			}
+		}
+
+		for (name, size) in added {
+			match size {
+				Some(size) => self.add_file(directory, name, *size)?,
+				None => self.add_directory(directory, name)?,
+			};
 		}
 
-		result.push((self.name.clone(), self.get_size()));
+		self.nodes[directory].listed = true;
+		return Ok(());
+	}
+
+	//runs a single shell command, returning the new working directory
+	fn run_command(&mut self, current: NodeId, line: &str) -> Result<NodeId, FsError> {
+		let parts: Vec<&str> = line.split_whitespace().skip(1).filter(|part| !part.starts_with('-')).collect();
+		match parts.as_slice() {
+			["cd", path] => {
+				let target = self.resolve(current, path)?;
+				if !self.is_directory(target) { return Err(FsError::NotADirectory(self.path(target))); }
+
+				return Ok(target);
+			}
+			["ls"] => {}
+			["rm", paths @ ..] if !paths.is_empty() => {
+				for path in paths {
+					let target = self.resolve(current, path)?;
+					let mut ancestor = Some(current);
+					while let Some(id) = ancestor {
+						if id == target { return Err(FsError::RemoveWorkingDirectory(self.path(target))); }
+						ancestor = self.parent(id);
+					}
 
-		return result;
+					self.remove(target)?;
+				}
+			}
+			["mkdir", paths @ ..] if !paths.is_empty() => {
+				for path in paths {
+					let (parent, name) = self.resolve_parent(current, path)?;
+					self.add_directory(parent, name)?;
+				}
+			}
+			["mv", source, destination] => {
+				let source = self.resolve(current, source)?;
+				match self.resolve(current, destination) {
+					Ok(target) if self.is_directory(target) => {
+						let name = self.nodes[source].name.clone();
+						self.move_node(source, target, &name)?;
+					}
+					Ok(target) => return Err(FsError::DuplicateName(self.path(target))),
+					Err(_) => {
+						let (parent, name) = self.resolve_parent(current, destination)?;
+						self.move_node(source, parent, name)?;
+					}
+				}
+			}
+			_ => return Err(FsError::UnknownCommand(line.to_string())),
+		}
+
+		return Ok(current);
 	}
 
-	pub fn from_file(filename: &str) -> Rc<RefCell<Folder>> {
-		let lines = get_lines(filename);
-		let root = Rc::new(RefCell::new(Folder::default()));
-		root.borrow_mut().name = "/".to_string();
-		root.borrow_mut().parent = None;
-		let mut current = Rc::clone(&root);
-		for line in lines {
+	///Rebuilds the filesystem from the lines of a terminal transcript.
+	///Besides `cd` and `ls`, the transcript may use `rm`, `mkdir` and `mv`.  The replay fails on
+	///the first inconsistency, such as changing into a directory that was never listed,
+	///a listing that contradicts an earlier one, or a name that appears twice in one listing.
+	pub fn replay(lines: &[String]) -> Result<FileSystem, ReplayError> {
+		let mut file_system = FileSystem::new();
+		let mut current = ROOT;
+		let mut listing: Option<(usize, Vec<ListingEntry>)> = None;  // the line of the `ls` being read and its output
+		for (index, line) in lines.iter().enumerate() {
+			let error = |error| ReplayError { line: index + 1, error };
 			let mut parts = line.split_whitespace();
 			match parts.next() {
 				Some("$") => {
-					match parts.next() {
-						Some("cd") => {
-							let folder_name = parts.next().unwrap();
-							if folder_name == "/" { current = Rc::clone(&root); }
-							else if folder_name == ".." {
-								let parent = current.borrow().parent.as_ref().unwrap().clone();
-								current = Rc::clone(&parent);
-							} else {
-								let mut next_folder = None;
-								for child in &current.borrow().children_folders {
-									if child.borrow().name == folder_name {
-										next_folder = Some(Rc::clone(child));
-										break;
-									}
-								}
-								
-								if next_folder.is_some() { current = next_folder.unwrap(); }
-							}
-						}
-						_ => {}
+					if let Some((ls_line, entries)) = listing.take() {
+						file_system.apply_listing(current, &entries).map_err(|error| ReplayError { line: ls_line, error })?;
 					}
+
+					current = file_system.run_command(current, line).map_err(error)?;
+					if let Some("ls") = parts.next() { listing = Some((index + 1, Vec::new())); }
 				}
-				Some("dir") => {
-					let mut folder = Folder::default();
-					folder.name = parts.next().unwrap().to_string();
-					folder.parent = Option::from(Rc::clone(&current));
-					current.borrow_mut().children_folders.push(Rc::new(RefCell::new(folder)));
-				}
-				Some(value) => {
-					let mut file = File::default();
-					file.size = value.parse::<u64>().unwrap();
-					file.name = parts.next().unwrap().to_string();
-					file.parent = Option::from(Rc::clone(&current));
-					current.borrow_mut().children_files.push(Rc::new(RefCell::new(file)));
+				Some(first) => {
+					let entries = match &mut listing {
+						Some((_, entries)) => entries,
+						None => return Err(error(FsError::UnexpectedOutput(line.clone()))),
+					};
+
+					let (name, size) = match (first, parts.next(), parts.next()) {
+						("dir", Some(name), None) => (name, None),
+						(size, Some(name), None) => (name, Some(size.parse::<u64>().map_err(|_| error(FsError::InvalidLine(line.clone())))?)),
+						_ => return Err(error(FsError::InvalidLine(line.clone()))),
+					};
+					entries.push((name.to_string(), size));
 				}
 				None => {}
 			}
 		}
 
-		return root;
+		if let Some((ls_line, entries)) = listing {
+			file_system.apply_listing(current, &entries).map_err(|error| ReplayError { line: ls_line, error })?;
+		}
+
+		return Ok(file_system);
+	}
+
+	fn to_json_node(&self, id: NodeId) -> JsonNode {
+		let name = self.name(id).to_string();
+		return if self.is_directory(id) {
+			JsonNode::Directory { name, size: self.size(id), children: self.children(id).iter().map(|&child| self.to_json_node(child)).collect() }
+		} else {
+			JsonNode::File { name, size: self.size(id) }
+		}
+	}
+
+	fn add_json_node(&mut self, parent: NodeId, node: &JsonNode) -> Result<(), FsError> {
+		match node {
+			JsonNode::Directory { name, children, .. } => {
+				let id = self.add_directory(parent, name)?;
+				for child in children { self.add_json_node(id, child)?; }
+			}
+			JsonNode::File { name, size } => { self.add_file(parent, name, *size)?; }
+		}
+
+		return Ok(());
+	}
+
+	///Writes the filesystem as a JSON document of nested nodes.  Every node has a "type" of
+	///"directory" or "file", a "name" and a "size", and directories also list their "children".
+	pub fn to_json(&self) -> String {
+		return serde_json::to_string_pretty(&self.to_json_node(ROOT)).unwrap();
+	}
+
+	///Reads a filesystem from a JSON document written by `to_json`.  The root must be a
+	///directory.  The sizes of directories may be left out since they are recomputed, and
+	///so may the children of empty directories.
+	pub fn from_json(json: &str) -> Result<FileSystem, FsError> {
+		let root: JsonNode = serde_json::from_str(json).map_err(|error| FsError::InvalidJson(error.to_string()))?;
+		let children = match &root {
+			JsonNode::Directory { children, .. } => children,
+			JsonNode::File { .. } => return Err(FsError::NotADirectory("/".to_string())),
+		};
+
+		let mut file_system = FileSystem::new();
+		for child in children { file_system.add_json_node(ROOT, child)?; }
+
+		return Ok(file_system);
+	}
+
+	///Rebuilds the filesystem from a file containing a terminal transcript.
+	pub fn from_file(filename: &str) -> FileSystem {
+		return FileSystem::replay(&get_lines(filename)).unwrap_or_else(|error| panic!("Invalid transcript: {}", error));
 	}
 }
 
-#[derive(Default)]
-struct File {
-	name: String,
-	size: u64,
-	parent: Option<Rc<RefCell<Folder>>>,
-}
\ No newline at end of file
+///The input is a terminal transcript of browsing a filesystem with `cd` and `ls`.
+///This function returns the sum of the sizes of all directories that are at most 100000 in size.
+pub fn get_sum_small_directories(filename: &str) -> u64 {
+	return FileSystem::from_file(filename).sum_small_directories(100000);
+}
+
+///The disk has a size of 70000000 and an update needs 30000000 of free space.
+///This function returns the size of the smallest directory that can be deleted to make room for the update.
+pub fn get_smallest_directory_to_delete(filename: &str) -> u64 {
+	let file_system = FileSystem::from_file(filename);
+	let directory = file_system.smallest_directory_to_delete(70000000, 30000000).expect("No directory is large enough");
+
+	return file_system.size(directory);
+}
@@ -1,108 +1,208 @@
+use std::fs;
 use crate::tasks::helper::get_lines;
 
-///this functions checks if a given character is greater than all characters in a string
-pub fn is_greater_than_all(number: char, array: &str) -> bool {
-	for i in 0..array.len() {
-		if number <= array.chars().nth(i).unwrap() { return false; }
-	}
+///The four directions one can look in from a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+	Up,
+	Down,
+	Left,
+	Right,
+}
 
-	return true;
+impl Direction {
+	pub const ALL: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
 }
 
-///this function returns the number of characters that are less than the given character
-///until a character greater than or equal to the given character is found
-pub fn get_number_of_less_than(number: char, array: &str) -> u32 {
-	let mut number_of_less_than = 0;
-	for i in 0..array.len() {
-		if number > array.chars().nth(i).unwrap() { number_of_less_than += 1; }
-		else {
-			number_of_less_than += 1;
-			break;
-		}
-	}
+///Everything known about a single tree.  The arrays are indexed by `Direction`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeView {
+	pub row: usize,
+	pub col: usize,
+	pub height: u8,
+	pub visible: [bool; 4],
+	pub distances: [u32; 4],
+	pub scenic_score: u64,
+}
+
+impl TreeView {
+	pub fn visible_from(&self, direction: Direction) -> bool { self.visible[direction as usize] }
+
+	pub fn viewing_distance(&self, direction: Direction) -> u32 { self.distances[direction as usize] }
 
-	return number_of_less_than;
+	///Returns whether the tree is visible from outside the forest in any direction.
+	pub fn is_visible(&self) -> bool { self.visible.iter().any(|&visible| visible) }
 }
 
-///The input is a file containing a grid of numbers representing tree heights.
-///A tree is visible from the outside if all the trees between it and the outside
-///in any of the four cardinal directions are shorter than it.
-///This function returns the number of visible trees.
-pub fn count_visible_trees(filename: &str) -> u32 {
-	let lines = get_lines(filename);
-	let mut visible_trees = 0;
-	for i in 0..lines.len() {
-		for j in 0..lines[i].len() {
-			let current = lines[i].chars().nth(j).unwrap();
-
-			// check if the current tree is visible from the left
-			if is_greater_than_all(current, &lines[i][0..j]) {
-				visible_trees += 1;
-				continue;
-			}
+///File formats for exporting the scenic scores of a forest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeatmapFormat {
+	///A plain text PGM image, with scores scaled linearly so the greatest score is white.
+	Pgm,
+	///One line of comma separated scores per row of trees.
+	Csv,
+}
 
-			// check if the current tree is visible from the right
-			if is_greater_than_all(current, &lines[i][j+1..]) {
-				visible_trees += 1;
-				continue;
-			}
+///A grid of tree heights, along with whether each tree is visible from the outside and
+///how many trees it can see in each direction.  Everything is computed up front in
+///O(rows * cols) by sweeping every row and column with a monotonic stack.
+pub struct Forest {
+	heights: Vec<Vec<u8>>,
+	visible: [Vec<Vec<bool>>; 4],   // indexed by Direction
+	distances: [Vec<Vec<u32>>; 4],  // indexed by Direction
+}
 
-			// check if the current tree is visible from the top
-			let mut top = String::new();
-			for k in 0..i { top.push(lines[k].chars().nth(j).unwrap()); }
+impl Forest {
+	///Builds a forest from a rectangular grid of heights, with the first row at the top.
+	pub fn new(heights: Vec<Vec<u8>>) -> Forest {
+		let rows = heights.len();
+		let cols = heights.first().map_or(0, |row| row.len());
+		assert!(heights.iter().all(|row| row.len() == cols), "every row of the forest must have the same length");
+
+		let mut forest = Forest {
+			heights,
+			visible: std::array::from_fn(|_| vec![vec![false; cols]; rows]),
+			distances: std::array::from_fn(|_| vec![vec![0; cols]; rows]),
+		};
+
+		for direction in Direction::ALL {
+			let lines: Vec<Vec<(usize, usize)>> = match direction {
+				Direction::Left => (0..rows).map(|i| (0..cols).map(|j| (i, j)).collect()).collect(),
+				Direction::Right => (0..rows).map(|i| (0..cols).rev().map(|j| (i, j)).collect()).collect(),
+				Direction::Up => (0..cols).map(|j| (0..rows).map(|i| (i, j)).collect()).collect(),
+				Direction::Down => (0..cols).map(|j| (0..rows).rev().map(|i| (i, j)).collect()).collect(),
+			};
+
+			for line in lines { forest.sweep(direction, &line); }
+		}
 
-			if is_greater_than_all(current, &top) {
-				visible_trees += 1;
-				continue;
-			}
+		return forest;
+	}
 
-			// check if the current tree is visible from the bottom
-			let mut bottom = String::new();
-			for k in i+1..lines.len() { bottom.push(lines[k].chars().nth(j).unwrap()); }
+	///Reads a file containing a grid of digits representing tree heights.
+	pub fn from_file(filename: &str) -> Forest {
+		let heights = get_lines(filename).iter()
+			.filter(|line| !line.is_empty())
+			.map(|line| line.chars().map(|c| c.to_digit(10).expect("Invalid tree height") as u8).collect())
+			.collect();
 
-			if is_greater_than_all(current, &bottom) {
-				visible_trees += 1;
-				continue;
+		return Forest::new(heights);
+	}
+
+	//walks along a line of trees that starts at the edge the trees are looking towards.
+	//The stack holds the positions along the line of trees that could still block the view
+	//of a later tree, from shortest on top to tallest at the bottom.
+	fn sweep(&mut self, direction: Direction, line: &[(usize, usize)]) {
+		let mut stack: Vec<usize> = Vec::new();
+		for (position, &(i, j)) in line.iter().enumerate() {
+			let height = self.heights[i][j];
+			while let Some(&top) = stack.last() {
+				let (top_i, top_j) = line[top];
+				if self.heights[top_i][top_j] >= height { break; }
+
+				stack.pop();
 			}
+
+			self.visible[direction as usize][i][j] = stack.is_empty();
+			self.distances[direction as usize][i][j] = match stack.last() {
+				Some(&blocker) => (position - blocker) as u32,
+				None => position as u32,
+			};
+			stack.push(position);
 		}
 	}
 
-	return visible_trees;
-}
+	pub fn rows(&self) -> usize { self.heights.len() }
 
-///Given a file containing a grid of numbers representing tree heights, this function returns
-///the greatest scenic score of all trees.  This is calculated by multiplying the number of visible
-///trees in all four directions from the tree.  The scenic score of a tree on the outside is 0,
-///since it has no trees in at least one direction.
-pub fn get_greatest_scenic_score(filename: &str) -> u32 {
-	let lines = get_lines(filename);
-	let mut greatest_scenic_score = 0;
-	for i in 1..lines.len()-1 {
-		for j in 1..lines[i].len()-1 {
-			let current = lines[i].chars().nth(j).unwrap();
-			let mut scenic_score = 1;
+	pub fn cols(&self) -> usize { self.heights.first().map_or(0, |row| row.len()) }
 
-			// get number of trees visible from the left
-			scenic_score *= get_number_of_less_than(current, &lines[i][0..j].chars().rev().collect::<String>());
+	pub fn heights(&self) -> &Vec<Vec<u8>> { &self.heights }
 
-			// get number of trees visible from the right
-			scenic_score *= get_number_of_less_than(current, &lines[i][j+1..]);
+	///Returns for every tree whether all trees between it and the edge in the given direction are shorter.
+	pub fn visible_from(&self, direction: Direction) -> &Vec<Vec<bool>> { &self.visible[direction as usize] }
 
-			// get number of trees visible from the top
-			let mut top = String::new();
-			for k in (0..i).rev() { top.push(lines[k].chars().nth(j).unwrap()); }
+	///Returns for every tree how many trees it can see in the given direction, stopping at
+	///the edge or at the first tree that is at least as tall.
+	pub fn viewing_distances(&self, direction: Direction) -> &Vec<Vec<u32>> { &self.distances[direction as usize] }
 
-			scenic_score *= get_number_of_less_than(current, &top);
+	///Returns everything known about the tree at the given position, or None if it is outside the forest.
+	pub fn tree(&self, row: usize, col: usize) -> Option<TreeView> {
+		if row >= self.rows() || col >= self.cols() { return None; }
 
-			// get number of trees visible from the bottom
-			let mut bottom = String::new();
-			for k in i+1..lines.len() { bottom.push(lines[k].chars().nth(j).unwrap()); }
+		let visible = Direction::ALL.map(|direction| self.visible[direction as usize][row][col]);
+		let distances = Direction::ALL.map(|direction| self.distances[direction as usize][row][col]);
+		return Some(TreeView {
+			row,
+			col,
+			height: self.heights[row][col],
+			visible,
+			distances,
+			scenic_score: distances.iter().map(|&distance| distance as u64).product(),
+		});
+	}
 
-			scenic_score *= get_number_of_less_than(current, &bottom);
+	///Returns for every tree whether it is visible from outside the forest in any direction.
+	pub fn visibility(&self) -> Vec<Vec<bool>> {
+		return (0..self.rows()).map(|i| {
+			(0..self.cols()).map(|j| Direction::ALL.iter().any(|&direction| self.visible[direction as usize][i][j])).collect()
+		}).collect();
+	}
+
+	///Returns for every tree the product of its viewing distances in all four directions.
+	pub fn scenic_scores(&self) -> Vec<Vec<u64>> {
+		return (0..self.rows()).map(|i| {
+			(0..self.cols()).map(|j| Direction::ALL.iter().map(|&direction| self.distances[direction as usize][i][j] as u64).product()).collect()
+		}).collect();
+	}
 
-			greatest_scenic_score = greatest_scenic_score.max(scenic_score);
+	///Renders the scenic score of every tree in the given format.
+	pub fn scenic_heatmap(&self, format: HeatmapFormat) -> String {
+		let scores = self.scenic_scores();
+		return match format {
+			HeatmapFormat::Pgm => {
+				let greatest = scores.iter().flatten().copied().max().unwrap_or(0).max(1);
+				let mut image = format!("P2\n{} {}\n255\n", self.cols(), self.rows());
+				for row in &scores {
+					let pixels: Vec<String> = row.iter().map(|&score| (score * 255 / greatest).to_string()).collect();
+					image.push_str(&pixels.join(" "));
+					image.push('\n');
+				}
+
+				image
+			}
+			HeatmapFormat::Csv => {
+				let mut csv = String::new();
+				for row in &scores {
+					let cells: Vec<String> = row.iter().map(|score| score.to_string()).collect();
+					csv.push_str(&cells.join(","));
+					csv.push('\n');
+				}
+
+				csv
+			}
 		}
 	}
 
-	return greatest_scenic_score;
-}
\ No newline at end of file
+	///Writes the scenic score heatmap to a file.
+	pub fn save_scenic_heatmap(&self, filename: &str, format: HeatmapFormat) -> std::io::Result<()> {
+		return fs::write(filename, self.scenic_heatmap(format));
+	}
+}
+
+///The input is a file containing a grid of numbers representing tree heights.
+///A tree is visible from the outside if all the trees between it and the outside
+///in any of the four cardinal directions are shorter than it.
+///This function returns the number of visible trees.
+pub fn count_visible_trees(filename: &str) -> u32 {
+	let forest = Forest::from_file(filename);
+	return forest.visibility().iter().flatten().filter(|&&visible| visible).count() as u32;
+}
+
+///Given a file containing a grid of numbers representing tree heights, this function returns
+///the greatest scenic score of all trees.  This is calculated by multiplying the number of visible
+///trees in all four directions from the tree.  The scenic score of a tree on the outside is 0,
+///since it has no trees in at least one direction.
+pub fn get_greatest_scenic_score(filename: &str) -> u32 {
+	let forest = Forest::from_file(filename);
+	return forest.scenic_scores().iter().flatten().copied().max().unwrap_or(0) as u32;
+}
@@ -1,78 +1,281 @@
-use crate::tasks::helper::get_lines;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
 
-fn get_x_y_distance(segment_a: &(i32, i32), segment_b: &(i32, i32)) -> (i32, i32) {
-	let x_distance = segment_a.0 - segment_b.0;
-	let y_distance = segment_a.1 - segment_b.1;
-	return (x_distance, y_distance);
+///A position with one coordinate per axis.  In two dimensions it is [x, y], with y growing upwards.
+pub type Point<const N: usize> = [i32; N];
+
+///A line of a move script that could not be understood.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidMove(pub String);
+
+impl fmt::Display for InvalidMove {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid move: {}", self.0)
+	}
+}
+
+impl std::error::Error for InvalidMove {}
+
+///Errors from running a move script read from a stream.
+#[derive(Debug)]
+pub enum ScriptError {
+	Io(io::Error),
+	InvalidMove(InvalidMove),
 }
 
-///Updates the locations of each segment of the rop after the head has moved.
-///If the segment is not adjacent to the previous segment, it is moved according to these rules:
-///if the segment is still in the same row or column as the previous segment, it is moved in the
-///direction of the previous segment.  If the segment is in a different row and column than the
-///previous segment, it is moved diagonally one space in the direction of the previous segment.
-fn update_rope_locations(rope: &mut Vec<(i32, i32)>) {
-	for i in 1..rope.len() {
-		let distance = get_x_y_distance(&rope[i-1], &rope[i]);
-		if distance.0.abs() <= 1 && distance.1.abs() <= 1 { continue; }
-
-		if distance.0 > 0 { rope[i].0 += 1; }
-		else if distance.0 < 0 { rope[i].0 -= 1; }
-
-		if distance.1 > 0 { rope[i].1 += 1; }
-		else if distance.1 < 0 { rope[i].1 -= 1; }
+impl fmt::Display for ScriptError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ScriptError::Io(error) => write!(f, "{}", error),
+			ScriptError::InvalidMove(error) => write!(f, "{}", error),
+		}
 	}
 }
 
-///The input of the file is a list of movements describing the movement of the head of a rope.
-///The head of the rope can move in 4 directions: up, down, left and right.  The tail of the rope
-///is always touching the head, either adjacent to it, diagonally adjacent to it, or overlapping
-///on the same spot.  After each movement, the tail of the rope is updated to be touching the
-///head in the same way as before.  The goal is to find the number of unique spots that the tail
-///of the rope has been in.
-pub fn get_unique_tail_spots(filename: &str, rope_length: usize) -> usize {
-	let lines = get_lines(filename);
-	let mut rope = vec![(0, 0); rope_length];
-	let mut unique_spots = vec![(0, 0)];
-	for line in lines {
-		let direction = line.chars().nth(0).unwrap();
-		let distance = line[2..].parse::<u32>().unwrap();
-		match direction {
-			'U' => {
-				for _ in 0..distance {
-					rope[0].1 += 1;
-					update_rope_locations(&mut rope);
-
-					if !unique_spots.contains(&rope[rope_length-1]) { unique_spots.push(rope[rope_length-1]); }
-				}
-			}
-			'D' => {
-				for _ in 0..distance {
-					rope[0].1 -= 1;
-					update_rope_locations(&mut rope);
+impl std::error::Error for ScriptError {}
 
-					if !unique_spots.contains(&rope[rope_length-1]) { unique_spots.push(rope[rope_length-1]); }
-				}
-			}
-			'L' => {
-				for _ in 0..distance {
-					rope[0].0 -= 1;
-					update_rope_locations(&mut rope);
+impl From<io::Error> for ScriptError {
+	fn from(error: io::Error) -> ScriptError { ScriptError::Io(error) }
+}
 
-					if !unique_spots.contains(&rope[rope_length-1]) { unique_spots.push(rope[rope_length-1]); }
-				}
-			}
-			'R' => {
-				for _ in 0..distance {
-					rope[0].0 += 1;
-					update_rope_locations(&mut rope);
+impl From<InvalidMove> for ScriptError {
+	fn from(error: InvalidMove) -> ScriptError { ScriptError::InvalidMove(error) }
+}
+
+///Moves the head of the rope `count` single steps of `delta`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move<const N: usize> {
+	pub delta: [i32; N],
+	pub count: u32,
+}
 
-					if !unique_spots.contains(&rope[rope_length-1]) { unique_spots.push(rope[rope_length-1]); }
+impl<const N: usize> Move<N> {
+	//parses a direction such as "U", "DL" or "+2-0", see `parse_moves`
+	fn direction(direction: &str) -> Option<[i32; N]> {
+		let mut delta = [0; N];
+		let mut chars = direction.chars().peekable();
+		while let Some(c) = chars.next() {
+			let (axis, sign) = match c {
+				'R' => (0, 1),
+				'L' => (0, -1),
+				'U' => (1, 1),
+				'D' => (1, -1),
+				'F' => (2, 1),
+				'B' => (2, -1),
+				'+' | '-' => {
+					let mut digits = String::new();
+					while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) { digits.push(digit); }
+
+					(digits.parse::<usize>().ok()?, if c == '+' { 1 } else { -1 })
 				}
+				_ => return None,
+			};
+
+			if axis >= N || delta[axis] != 0 { return None; }
+			delta[axis] = sign;
+		}
+
+		return if delta.iter().all(|&d| d == 0) { None } else { Some(delta) }
+	}
+}
+
+///Parses a line of a move script.  A line holds one or more moves separated by whitespace
+///or commas, each made of a direction and a step count, such as "R 4" or "UL 2, D 3".
+///A direction steps along one or more axes at once: R/L for the first axis, U/D for the second
+///and F/B for the third, or a sign followed by the 0-based index of any axis, such as "+2" for
+///the third axis.
+pub fn parse_moves<const N: usize>(line: &str) -> Result<Vec<Move<N>>, InvalidMove> {
+	let tokens: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',').filter(|token| !token.is_empty()).collect();
+	if !tokens.len().is_multiple_of(2) { return Err(InvalidMove(line.to_string())); }
+
+	let mut moves = Vec::with_capacity(tokens.len() / 2);
+	for pair in tokens.chunks(2) {
+		let delta = Move::direction(pair[0]).ok_or_else(|| InvalidMove(line.to_string()))?;
+		let count = pair[1].parse::<u32>().map_err(|_| InvalidMove(line.to_string()))?;
+		moves.push(Move { delta, count });
+	}
+
+	return Ok(moves);
+}
+
+///Parses every line of a move script.  Blank lines are ignored.
+pub fn parse_script<const N: usize>(lines: &[String]) -> Result<Vec<Move<N>>, InvalidMove> {
+	let mut moves = Vec::new();
+	for line in lines { moves.extend(parse_moves(line)?); }
+
+	return Ok(moves);
+}
+
+//the number of bits in each chunk of a `VisitedSet`
+const CHUNK_BITS: usize = 4096;
+
+///A set of positions stored as bitmaps over fixed size chunks of space, looked up by
+///their chunk coordinates, so the set only takes up room where positions were added.
+///The chunk of the last insert is remembered, and since a rope moves one step at a time,
+///most inserts skip the lookup entirely.  This is much faster than hashing every position.
+#[derive(Debug, Clone)]
+pub struct VisitedSet<const N: usize> {
+	side: i32,                               // the length of a chunk along each axis
+	index: HashMap<[i32; N], usize>,         // the position of each chunk in `chunks`
+	chunks: Vec<([i32; N], Vec<u64>)>,       // the chunk coordinates and bits of every chunk
+	last: Option<([i32; N], usize)>,         // the chunk of the last insert
+	len: usize,
+}
+
+impl<const N: usize> Default for VisitedSet<N> {
+	fn default() -> VisitedSet<N> {
+		// the largest side that keeps side^N within CHUNK_BITS
+		let mut side: usize = 2;
+		while (side + 1).checked_pow(N as u32).is_some_and(|bits| bits <= CHUNK_BITS) { side += 1; }
+
+		VisitedSet { side: side as i32, index: HashMap::new(), chunks: Vec::new(), last: None, len: 0 }
+	}
+}
+
+impl<const N: usize> VisitedSet<N> {
+	pub fn new() -> VisitedSet<N> { VisitedSet::default() }
+
+	//splits a position into its chunk coordinates and the index of its bit within the chunk
+	fn locate(&self, point: Point<N>) -> ([i32; N], usize) {
+		let chunk = point.map(|coordinate| coordinate.div_euclid(self.side));
+		let bit = point.iter().rev().fold(0, |bit, &coordinate| bit * self.side as usize + coordinate.rem_euclid(self.side) as usize);
+
+		return (chunk, bit);
+	}
+
+	//the position of a chunk in `chunks`, if it exists
+	fn find_chunk(&self, chunk: [i32; N]) -> Option<usize> {
+		return match self.last {
+			Some((last, position)) if last == chunk => Some(position),
+			_ => self.index.get(&chunk).copied(),
+		}
+	}
+
+	///Adds a position, returning whether it was not in the set before.
+	pub fn insert(&mut self, point: Point<N>) -> bool {
+		let (chunk, bit) = self.locate(point);
+		let position = match self.find_chunk(chunk) {
+			Some(position) => position,
+			None => {
+				let words = (self.side as usize).pow(N as u32).div_ceil(64);
+				self.chunks.push((chunk, vec![0; words]));
+				self.index.insert(chunk, self.chunks.len() - 1);
+				self.chunks.len() - 1
 			}
-			_ => panic!("Invalid direction: {}", direction)
+		};
+
+		self.last = Some((chunk, position));
+		let word = &mut self.chunks[position].1[bit / 64];
+		if *word & (1 << (bit % 64)) != 0 { return false; }
+
+		*word |= 1 << (bit % 64);
+		self.len += 1;
+		return true;
+	}
+
+	pub fn contains(&self, point: Point<N>) -> bool {
+		let (chunk, bit) = self.locate(point);
+		return self.find_chunk(chunk).is_some_and(|position| self.chunks[position].1[bit / 64] & (1 << (bit % 64)) != 0);
+	}
+
+	pub fn len(&self) -> usize { self.len }
+
+	pub fn is_empty(&self) -> bool { self.len == 0 }
+
+	///Iterates over every position in the set, grouped by chunk in the order the chunks were first used.
+	pub fn iter(&self) -> impl Iterator<Item = Point<N>> + '_ {
+		let side = self.side;
+		return self.chunks.iter().flat_map(move |(chunk, words)| {
+			words.iter().enumerate()
+				.filter(|(_, &word)| word != 0)
+				.flat_map(|(word_index, &word)| (0..64).filter(move |bit| word & (1 << bit) != 0).map(move |bit| word_index * 64 + bit))
+				.map(move |mut bit| {
+					chunk.map(|chunk_coordinate| {
+						let offset = (bit % side as usize) as i32;
+						bit /= side as usize;
+						chunk_coordinate * side + offset
+					})
+				})
+		});
+	}
+}
+
+///A rope made of knots in N dimensions that all start at the origin.  The first knot is the head.
+///Whenever a knot ends up more than `slack` steps away from the knot before it, counting
+///diagonal steps as one, it moves one step towards that knot along every axis where they differ.
+///Every position each knot has been in is recorded.  The puzzle rope is `Rope<2>` with a slack of 1.
+pub struct Rope<const N: usize> {
+	knots: Vec<Point<N>>,
+	slack: i32,
+	visited: Vec<VisitedSet<N>>,
+}
+
+impl<const N: usize> Rope<N> {
+	pub fn new(knot_count: usize, slack: i32) -> Rope<N> {
+		assert!(knot_count > 0, "a rope needs at least one knot");
+		let mut visited = vec![VisitedSet::new(); knot_count];
+		for set in &mut visited { set.insert([0; N]); }
+
+		Rope { knots: vec![[0; N]; knot_count], slack, visited }
+	}
+
+	pub fn knots(&self) -> &[Point<N>] { &self.knots }
+
+	///Moves the head a single step of `delta`, and then lets the other knots follow.
+	pub fn step(&mut self, delta: [i32; N]) {
+		for (coordinate, d) in self.knots[0].iter_mut().zip(delta) { *coordinate += d; }
+		self.visited[0].insert(self.knots[0]);
+
+		for i in 1..self.knots.len() {
+			let (leader, follower) = (self.knots[i-1], self.knots[i]);
+			if (0..N).all(|axis| (leader[axis] - follower[axis]).abs() <= self.slack) { break; }
+
+			for axis in 0..N { self.knots[i][axis] += (leader[axis] - follower[axis]).signum(); }
+			self.visited[i].insert(self.knots[i]);
+		}
+	}
+
+	pub fn apply(&mut self, movement: &Move<N>) {
+		for _ in 0..movement.count { self.step(movement.delta); }
+	}
+
+	///Applies every move produced by an iterator, such as a generator of random moves.
+	pub fn apply_all<I: IntoIterator<Item = Move<N>>>(&mut self, moves: I) {
+		for movement in moves { self.apply(&movement); }
+	}
+
+	///Reads a move script line by line and applies each move as soon as it is read,
+	///so the script never has to fit in memory.  Stops at the first invalid line.
+	pub fn run_script<R: BufRead>(&mut self, reader: R) -> Result<(), ScriptError> {
+		for line in reader.lines() {
+			for movement in parse_moves(&line?)? { self.apply(&movement); }
 		}
+
+		return Ok(());
 	}
 
-	return unique_spots.len();
-}
\ No newline at end of file
+	///Returns every position the given knot has been in.
+	pub fn visited(&self, knot: usize) -> &VisitedSet<N> { &self.visited[knot] }
+
+	///Returns every position each knot has been in, starting with the head.
+	pub fn visited_sets(&self) -> &Vec<VisitedSet<N>> { &self.visited }
+
+	///Returns every position the last knot has been in.
+	pub fn tail_visited(&self) -> &VisitedSet<N> { self.visited.last().unwrap() }
+}
+
+///The input of the file is a list of movements describing the movement of the head of a rope.
+///The head of the rope can move in 4 directions: up, down, left and right.  The tail of the rope
+///is always touching the head, either adjacent to it, diagonally adjacent to it, or overlapping
+///on the same spot.  After each movement, the tail of the rope is updated to be touching the
+///head in the same way as before.  The goal is to find the number of unique spots that the tail
+///of the rope has been in.
+pub fn get_unique_tail_spots(filename: &str, rope_length: usize) -> usize {
+	let file = File::open(filename).expect("no such file");
+	let mut rope = Rope::<2>::new(rope_length, 1);
+	rope.run_script(BufReader::new(file)).expect("Invalid move script");
+
+	return rope.tail_visited().len();
+}